@@ -0,0 +1,124 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Time of one game tick in headless app
+//!
+//! Runs `FixedUpdate` of [`GamePlugin`] without window and rendering, so
+//! simulation step comes together with moving snake sprites and meat sync.
+
+use bevy::{input::InputPlugin, prelude::*, window::WindowFocused};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use snake_rs::{
+    replace_simulation,
+    sim::{BorderMode, SimMeat, SimRules, SimSnake, SimState, SimStats, SnakeDirection},
+    GameConfig, GamePlugin, GameState,
+};
+
+/// Board with `snakes` snakes of `length` elements and `meats` meats
+///
+/// Every snake fills its own wrapped column and chases own tail forever,
+/// meat lies in free columns between snakes.
+fn board(snakes: usize, length: usize, meats: usize) -> SimState {
+    let height = length as i32 + 1;
+    let mut state = SimState::new(SimRules {
+        map_size: IVec2::new(snakes as i32 * 2, height),
+        border_mode: BorderMode::Wrap,
+        meat_spawn_interval: u64::MAX,
+        meat_limit: usize::MAX,
+        starvation_ticks: 0,
+        snakes,
+        ..SimRules::default()
+    });
+    for (index, snake) in state.snakes.iter_mut().enumerate() {
+        let x = index as i32 * 2;
+        *snake = SimSnake {
+            direction: SnakeDirection::Up,
+            body: (0..height - 1).rev().map(|y| IVec2::new(x, y)).collect(),
+            alive: true,
+            stats: SimStats::default(),
+            hunger: 0,
+            death: None,
+            killer: None,
+        };
+    }
+    let free = (0..height).flat_map(|y| (0..snakes as i32).map(move |x| IVec2::new(x * 2 + 1, y)));
+    state.meats = free
+        .take(meats)
+        .zip(0..)
+        .map(|(position, id)| SimMeat {
+            id,
+            position,
+            hue: 0.,
+        })
+        .collect();
+    state.rebuild_grid();
+    state
+}
+
+/// Game app without window showing `state`
+fn app(state: SimState) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        InputPlugin,
+        AssetPlugin::default(),
+    ))
+    // Interface & pause menu expect text and window
+    .init_asset::<Font>()
+    .add_event::<WindowFocused>()
+    .insert_resource(GameConfig {
+        players: state.snakes.len(),
+        bots: Vec::new(),
+        ..GameConfig::default()
+    })
+    .insert_state(GameState::MainMenu)
+    .add_plugins(GamePlugin);
+    app.update();
+    app.world
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::InGame);
+    app.update();
+
+    // Prepared board replaces the new game, first tick places every element
+    replace_simulation(&mut app.world, state);
+    tick(&mut app);
+    app
+}
+
+fn tick(app: &mut App) {
+    app.world.run_schedule(FixedUpdate);
+}
+
+/// Measure one tick of app with prepared board
+fn bench_tick(c: &mut Criterion, group: &str, cases: &[(String, SimState)]) {
+    let mut group = c.benchmark_group(group);
+    for (name, state) in cases {
+        let cells: usize = state.snakes.iter().map(|snake| snake.body.len()).sum();
+        group.throughput(Throughput::Elements((cells + state.meats.len()) as u64));
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            let mut app = app(state.clone());
+            b.iter(|| tick(&mut app));
+        });
+    }
+    group.finish();
+}
+
+fn long_snake(c: &mut Criterion) {
+    let cases: Vec<_> = [1_000, 10_000, 50_000]
+        .into_iter()
+        .map(|length| (length.to_string(), board(1, length, 10)))
+        .collect();
+    bench_tick(c, "app/long_snake", &cases);
+}
+
+fn many_meats(c: &mut Criterion) {
+    let cases: Vec<_> = [10, 1_000, 10_000]
+        .into_iter()
+        .map(|meats| (meats.to_string(), board(4, 10_000, meats)))
+        .collect();
+    bench_tick(c, "app/many_meats", &cases);
+}
+
+criterion_group!(benches, long_snake, many_meats);
+criterion_main!(benches);
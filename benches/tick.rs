@@ -0,0 +1,189 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Time of one game tick on big boards
+//!
+//! Movement, collisions, eating and meat spawn all run in `SimState::step`,
+//! every measured tick starts from the same prepared board.
+
+use std::collections::VecDeque;
+
+use bevy::math::IVec2;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use snake_rs::{
+    bot::{self, BotLevel},
+    sim::{SimMeat, SimRules, SimSnake, SimState, SimStats, SnakeDirection},
+    GameConfig, GameRng,
+};
+
+/// Board width, snakes are laid out in rows of this width
+const WIDTH: i32 = 250;
+
+/// Board with `snakes` snakes of `length` elements and `meats` meats
+///
+/// Every snake zigzags through its own band of rows and looks up into a free
+/// row, so nobody dies during measured tick. With `eating` meat lies right
+/// in front of every head.
+fn board(snakes: usize, length: usize, meats: usize, eating: bool) -> SimState {
+    let rows = length.div_ceil(WIDTH as usize) as i32;
+    // Free row above every band and room for meat on top
+    let height = (rows + 1) * snakes as i32 + 2 + meats as i32 / WIDTH;
+    let mut state = SimState::new(SimRules {
+        map_size: IVec2::new(WIDTH, height),
+        // Spawn is measured separately
+        meat_spawn_interval: u64::MAX,
+        meat_limit: usize::MAX,
+        snakes,
+        ..SimRules::default()
+    });
+
+    for (index, snake) in state.snakes.iter_mut().enumerate() {
+        let band = index as i32 * (rows + 1);
+        let mut body: VecDeque<IVec2> = (0..length as i32)
+            .map(|step| {
+                let row = step / WIDTH;
+                let column = step % WIDTH;
+                let x = if row % 2 == 0 {
+                    column
+                } else {
+                    WIDTH - 1 - column
+                };
+                IVec2::new(x, band + row)
+            })
+            .collect();
+        // Path goes from tail to head
+        body.make_contiguous().reverse();
+        *snake = SimSnake {
+            direction: SnakeDirection::Up,
+            body,
+            alive: true,
+            stats: SimStats::default(),
+            hunger: 0,
+            death: None,
+            killer: None,
+        };
+    }
+
+    // Meat goes to free rows on top
+    let top = (rows + 1) * snakes as i32;
+    let free = (top..height).flat_map(|y| (0..WIDTH).map(move |x| IVec2::new(x, y)));
+    let mut positions: Vec<IVec2> = if eating {
+        state
+            .snakes
+            .iter()
+            .map(|snake| snake.head() + IVec2::Y)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    positions.extend(free.take(meats));
+    state.meats = positions
+        .into_iter()
+        .zip(0..)
+        .map(|(position, id)| SimMeat {
+            id,
+            position,
+            hue: 0.,
+        })
+        .collect();
+    state.rebuild_grid();
+    state
+}
+
+/// Measure one tick of prepared board
+fn bench_step(c: &mut Criterion, group: &str, cases: &[(String, SimState)]) {
+    let mut group = c.benchmark_group(group);
+    for (name, state) in cases {
+        let cells: usize = state.snakes.iter().map(|snake| snake.body.len()).sum();
+        group.throughput(Throughput::Elements(cells as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), state, |b, state| {
+            let inputs = vec![None; state.snakes.len()];
+            b.iter_batched_ref(
+                || (state.clone(), GameRng::new(0)),
+                |(state, rng)| state.step(&inputs, rng),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn long_snake(c: &mut Criterion) {
+    let cases: Vec<_> = [1_000, 10_000, 50_000]
+        .into_iter()
+        .map(|length| (length.to_string(), board(1, length, 10, false)))
+        .collect();
+    bench_step(c, "tick/long_snake", &cases);
+}
+
+fn many_snakes(c: &mut Criterion) {
+    let cases: Vec<_> = [4, 16, 64]
+        .into_iter()
+        .map(|snakes| (snakes.to_string(), board(snakes, 1_000, 10, false)))
+        .collect();
+    bench_step(c, "tick/many_snakes", &cases);
+}
+
+fn many_meats(c: &mut Criterion) {
+    let cases: Vec<_> = [10, 1_000, 10_000]
+        .into_iter()
+        .map(|meats| (meats.to_string(), board(4, 1_000, meats, false)))
+        .collect();
+    bench_step(c, "tick/many_meats", &cases);
+}
+
+fn eating(c: &mut Criterion) {
+    let cases: Vec<_> = [1_000, 10_000]
+        .into_iter()
+        .map(|length| (length.to_string(), board(4, length, 10, true)))
+        .collect();
+    bench_step(c, "tick/eating", &cases);
+}
+
+fn meat_spawn(c: &mut Criterion) {
+    let cases: Vec<_> = [1_000, 10_000]
+        .into_iter()
+        .map(|length| {
+            let mut state = board(4, length, 10, false);
+            state.rules.meat_spawn_interval = 1;
+            (length.to_string(), state)
+        })
+        .collect();
+    bench_step(c, "tick/meat_spawn", &cases);
+}
+
+/// Thinking of one bot on default board in the middle of the game
+fn bots(c: &mut Criterion) {
+    let config = GameConfig {
+        bots: vec![BotLevel::Hard; 3],
+        ..GameConfig::default()
+    };
+    let mut state = SimState::new(config.rules());
+    let mut rng = GameRng::new(0);
+    for _ in 0..500 {
+        let inputs: Vec<_> = (0..state.snakes.len())
+            .map(|index| bot::think(&state, index, BotLevel::Hard))
+            .collect();
+        state.step(&inputs, &mut rng);
+    }
+
+    let mut group = c.benchmark_group("bot");
+    for level in [BotLevel::Easy, BotLevel::Normal, BotLevel::Hard] {
+        group.bench_function(level.to_string(), |b| {
+            b.iter(|| bot::think(&state, 0, level))
+        });
+    }
+    group.bench_function("autopilot", |b| b.iter(|| bot::autopilot(&state, 0)));
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    long_snake,
+    many_snakes,
+    many_meats,
+    eating,
+    meat_spawn,
+    bots
+);
+criterion_main!(benches);
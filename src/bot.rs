@@ -0,0 +1,480 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Computer-controlled snakes
+//!
+//! Bots see the board only through [`SimState`] cell queries and answer with
+//! direction for the next tick, like a player pressing keys.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    str::FromStr,
+};
+
+use bevy::math::{IVec2, Vec2Swizzles};
+use serde::{Deserialize, Serialize};
+
+use crate::sim::{BorderMode, SimSnake, SimState, SnakeDirection};
+
+/// Difficulty of bot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BotLevel {
+    /// Turns towards nearest meat, looks only at the next cell
+    Easy,
+    /// Follows shortest path to nearest meat
+    #[default]
+    Normal,
+    /// Follows path only when it has room after it, avoids head-on crashes
+    Hard,
+}
+
+impl fmt::Display for BotLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BotLevel::Easy => "easy",
+            BotLevel::Normal => "normal",
+            BotLevel::Hard => "hard",
+        })
+    }
+}
+
+impl FromStr for BotLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "easy" => Ok(BotLevel::Easy),
+            "normal" => Ok(BotLevel::Normal),
+            "hard" => Ok(BotLevel::Hard),
+            _ => Err(format!("unknown bot level {value}")),
+        }
+    }
+}
+
+const DIRECTIONS: [SnakeDirection; 4] = [
+    SnakeDirection::Up,
+    SnakeDirection::Down,
+    SnakeDirection::Left,
+    SnakeDirection::Right,
+];
+
+/// Pick direction of snake for the next tick, `None` keeps direction
+pub fn think(state: &SimState, snake: usize, level: BotLevel) -> Option<SnakeDirection> {
+    let board = Board::new(state, snake)?;
+    let choice = match level {
+        BotLevel::Easy => board.closest_to_meat(&board.moves),
+        BotLevel::Normal => board
+            .path_to_meat()
+            .or_else(|| board.closest_to_meat(&board.moves)),
+        BotLevel::Hard => board.careful(),
+    };
+    board.turn(choice)
+}
+
+/// Solver driving player snake
+///
+/// Follows Hamiltonian cycle of the board and cuts corners towards meat,
+/// acts like hard bot when board has no cycle or other snake is in the way.
+pub fn autopilot(state: &SimState, snake: usize) -> Option<SnakeDirection> {
+    let board = Board::new(state, snake)?;
+    let choice = board.cycle_shortcut().or_else(|| board.careful());
+    board.turn(choice)
+}
+
+/// Free cells kept in front of the tail while cutting corners, snake grows
+/// into them
+const GROW_MARGIN: i32 = 3;
+
+/// Board as seen by one bot
+struct Board<'a> {
+    state: &'a SimState,
+    snake: &'a SimSnake,
+    index: usize,
+    /// Moves which do not crash right now
+    moves: Vec<(SnakeDirection, IVec2)>,
+}
+
+impl<'a> Board<'a> {
+    /// Board for alive snake
+    fn new(state: &'a SimState, index: usize) -> Option<Self> {
+        let snake = state.snakes.get(index).filter(|snake| snake.alive)?;
+        let mut board = Self {
+            state,
+            snake,
+            index,
+            moves: Vec::new(),
+        };
+        board.moves = DIRECTIONS
+            .into_iter()
+            .filter(|direction| !direction.is_oposite(&snake.direction))
+            .filter_map(|direction| Some((direction, state.neighbour(snake.head(), direction)?)))
+            .filter(|(_, cell)| board.is_free(*cell))
+            .collect();
+        Some(board)
+    }
+
+    /// Input for chosen direction, `None` when snake already goes there
+    fn turn(&self, choice: Option<SnakeDirection>) -> Option<SnakeDirection> {
+        choice.filter(|direction| *direction != self.snake.direction)
+    }
+
+    /// Moves with enough room after them to fit the whole snake
+    fn roomy(&self, moves: &[(SnakeDirection, IVec2)]) -> Vec<(SnakeDirection, IVec2)> {
+        let length = self.snake.body.len();
+        moves
+            .iter()
+            .copied()
+            .filter(|(_, cell)| self.room(*cell, length) >= length)
+            .collect()
+    }
+
+    /// Path to meat with room after it, avoids head-on crashes
+    fn careful(&self) -> Option<SnakeDirection> {
+        // Cells where longer or same snake may put its head
+        let danger = self.head_on_danger();
+        let safe: Vec<_> = self
+            .moves
+            .iter()
+            .copied()
+            .filter(|(_, cell)| !danger.contains(cell))
+            .collect();
+        let moves = if safe.is_empty() { &self.moves } else { &safe };
+
+        let roomy = self.roomy(moves);
+        self.path_to_meat()
+            .filter(|direction| roomy.iter().any(|(roomy, _)| roomy == direction))
+            .or_else(|| self.closest_to_meat(&roomy))
+            .or_else(|| {
+                // Trapped, take the biggest room
+                moves
+                    .iter()
+                    .max_by_key(|(_, cell)| self.room(*cell, usize::MAX))
+                    .map(|(direction, _)| *direction)
+            })
+    }
+
+    /// Move along Hamiltonian cycle, skipping part of it towards meat
+    ///
+    /// Body lies behind the head on the cycle while snake never passes its
+    /// tail, so way along the cycle stays free. Body which does not follow
+    /// the cycle (snake was driven by player) only follows the cycle, till
+    /// the tail comes onto it.
+    fn cycle_shortcut(&self) -> Option<SnakeDirection> {
+        let cycle = Cycle::new(self.state.rules.map_size)?;
+        let body = &self.snake.body;
+        let head = self.snake.head();
+        let free_ahead = cycle.steps(head, *body.back()?) - 1;
+        let on_cycle = body
+            .iter()
+            .zip(body.iter().skip(1))
+            .all(|(segment, next)| cycle.steps(*next, *segment) == 1);
+        // Long snake needs the whole cycle
+        let max_step = if on_cycle && body.len() * 2 < cycle.len() as usize {
+            (free_ahead - GROW_MARGIN).max(1)
+        } else {
+            1
+        };
+        self.moves
+            .iter()
+            .map(|(direction, cell)| (*direction, *cell, cycle.steps(head, *cell)))
+            .filter(|(_, _, step)| (1..=max_step).contains(step))
+            .min_by_key(|(_, cell, step)| {
+                let to_meat = self
+                    .state
+                    .meats
+                    .iter()
+                    .map(|meat| cycle.steps(*cell, meat.position))
+                    .min();
+                (to_meat.unwrap_or(0), *step)
+            })
+            .map(|(direction, ..)| direction)
+    }
+
+    /// Snake may move to the cell
+    fn is_free(&self, cell: IVec2) -> bool {
+        !self.state.has_snake(cell)
+    }
+
+    /// Steps between cells
+    fn distance(&self, a: IVec2, b: IVec2) -> i32 {
+        let delta = (a - b).abs();
+        match self.state.rules.border_mode {
+            BorderMode::Wrap => {
+                let size = self.state.rules.map_size;
+                delta.x.min(size.x - delta.x) + delta.y.min(size.y - delta.y)
+            }
+            BorderMode::Solid => delta.x + delta.y,
+        }
+    }
+
+    /// Move which gets closer to nearest meat
+    fn closest_to_meat(&self, moves: &[(SnakeDirection, IVec2)]) -> Option<SnakeDirection> {
+        moves
+            .iter()
+            .min_by_key(|(_, cell)| {
+                self.state
+                    .meats
+                    .iter()
+                    .map(|meat| self.distance(*cell, meat.position))
+                    .min()
+                    .unwrap_or(0)
+            })
+            .map(|(direction, _)| *direction)
+    }
+
+    /// First move of shortest path to reachable meat
+    fn path_to_meat(&self) -> Option<SnakeDirection> {
+        let head = self.snake.head();
+        let current = self.snake.direction;
+        let mut first_moves = HashMap::from([(head, None)]);
+        let mut queue = VecDeque::from([head]);
+        while let Some(cell) = queue.pop_front() {
+            let first = first_moves[&cell];
+            if self.state.has_meat(cell) {
+                return first;
+            }
+            for direction in DIRECTIONS {
+                // Snake can not turn back
+                if cell == head && direction.is_oposite(&current) {
+                    continue;
+                }
+                let Some(next) = self.state.neighbour(cell, direction) else {
+                    continue;
+                };
+                if !self.is_free(next) || first_moves.contains_key(&next) {
+                    continue;
+                }
+                first_moves.insert(next, first.or(Some(direction)));
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+
+    /// Count of free cells reachable from `start`, stops after `limit`
+    fn room(&self, start: IVec2, limit: usize) -> usize {
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            if visited.len() >= limit {
+                break;
+            }
+            for direction in DIRECTIONS {
+                let Some(next) = self.state.neighbour(cell, direction) else {
+                    continue;
+                };
+                if self.is_free(next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        visited.len()
+    }
+
+    /// Cells next to heads of snakes which win or tie head-on crash
+    fn head_on_danger(&self) -> HashSet<IVec2> {
+        let length = self.snake.body.len();
+        self.state
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|(index, other)| {
+                *index != self.index && other.alive && other.body.len() >= length
+            })
+            .flat_map(|(_, other)| {
+                DIRECTIONS
+                    .into_iter()
+                    .filter_map(|direction| self.state.neighbour(other.head(), direction))
+            })
+            .collect()
+    }
+}
+
+/// Hamiltonian cycle of the board
+///
+/// Goes by rows between second and last column and returns by the first
+/// column, so it needs even count of rows (or columns for transposed cycle).
+struct Cycle {
+    size: IVec2,
+    transposed: bool,
+}
+
+impl Cycle {
+    fn new(size: IVec2) -> Option<Self> {
+        if size.y % 2 == 0 {
+            Some(Self {
+                size,
+                transposed: false,
+            })
+        } else if size.x % 2 == 0 {
+            Some(Self {
+                size: size.yx(),
+                transposed: true,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn len(&self) -> i32 {
+        self.size.x * self.size.y
+    }
+
+    /// Position of cell on the cycle
+    fn index(&self, cell: IVec2) -> i32 {
+        let IVec2 { x, y } = if self.transposed { cell.yx() } else { cell };
+        let IVec2 {
+            x: width,
+            y: height,
+        } = self.size;
+        if x == 0 {
+            // Way back by the first column
+            return if y == 0 {
+                0
+            } else {
+                height * (width - 1) + height - y
+            };
+        }
+        let row = 1 + y * (width - 1);
+        if y % 2 == 0 {
+            row + x - 1
+        } else {
+            row + width - 1 - x
+        }
+    }
+
+    /// Steps from one cell to another along the cycle
+    fn steps(&self, from: IVec2, to: IVec2) -> i32 {
+        (self.index(to) - self.index(from)).rem_euclid(self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::{
+        sim::{SimMeat, SimRules},
+        GameRng,
+    };
+
+    /// Cells of the board in order of the cycle
+    fn cycle_cells(cycle: &Cycle, size: IVec2) -> Vec<IVec2> {
+        let mut cells: Vec<_> = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| IVec2::new(x, y)))
+            .collect();
+        cells.sort_by_key(|cell| cycle.index(*cell));
+        cells
+    }
+
+    fn rules(size: IVec2) -> SimRules {
+        SimRules {
+            map_size: size,
+            border_mode: BorderMode::Solid,
+            snakes: 1,
+            players: 1,
+            starvation_ticks: 0,
+            ..SimRules::default()
+        }
+    }
+
+    #[test]
+    fn cycle_visits_every_cell_once() {
+        for (width, height) in [(4, 4), (6, 4), (5, 4), (4, 5), (7, 6), (2, 3)] {
+            let size = IVec2::new(width, height);
+            let cycle = Cycle::new(size).unwrap();
+            let cells = cycle_cells(&cycle, size);
+            let indices: Vec<_> = cells.iter().map(|cell| cycle.index(*cell)).collect();
+            assert_eq!(indices, (0..width * height).collect::<Vec<_>>(), "{size}");
+            // Every step goes to neighbour cell, last one closes the cycle
+            for (cell, next) in cells.iter().zip(cells.iter().cycle().skip(1)) {
+                assert_eq!(
+                    (*cell - *next).abs().to_array().iter().sum::<i32>(),
+                    1,
+                    "{size}: {cell} {next}"
+                );
+            }
+        }
+        assert!(Cycle::new(IVec2::new(5, 5)).is_none());
+    }
+
+    #[test]
+    fn shortcut_stays_ahead_of_body() {
+        let size = IVec2::new(8, 8);
+        let cycle = Cycle::new(size).unwrap();
+        let cells = cycle_cells(&cycle, size);
+        for start in [0, 13, 30, 50] {
+            let body: VecDeque<_> = (0..10).rev().map(|i| cells[(start + i) % 64]).collect();
+            for meat in &cells {
+                let mut state = SimState::new(rules(size));
+                let head = body[0];
+                if body.contains(meat) {
+                    continue;
+                }
+                state.snakes[0].body = body.clone();
+                state.snakes[0].direction = DIRECTIONS
+                    .into_iter()
+                    .find(|direction| state.neighbour(body[1], *direction) == Some(head))
+                    .unwrap();
+                state.meats = vec![SimMeat {
+                    id: 0,
+                    position: *meat,
+                    hue: 0.,
+                }];
+                state.rebuild_grid();
+
+                let board = Board::new(&state, 0).unwrap();
+                let direction = board.cycle_shortcut().unwrap();
+                let cell = state.neighbour(head, direction).unwrap();
+                // Way along the cycle to the new cell is free
+                for step in 1..=cycle.steps(head, cell) {
+                    let passed = cells[((cycle.index(head) + step) % 64) as usize];
+                    assert!(!body.contains(&passed), "meat {meat}: {passed} is body");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn body_off_cycle_follows_cycle() {
+        let size = IVec2::new(8, 8);
+        let cycle = Cycle::new(size).unwrap();
+        let mut state = SimState::new(rules(size));
+        // Vertical body crosses rows of the cycle
+        state.snakes[0].body = (2..6).rev().map(|y| IVec2::new(4, y)).collect();
+        state.snakes[0].direction = SnakeDirection::Up;
+        state.meats = vec![SimMeat {
+            id: 0,
+            position: IVec2::new(1, 7),
+            hue: 0.,
+        }];
+        state.rebuild_grid();
+
+        let board = Board::new(&state, 0).unwrap();
+        let head = state.snakes[0].head();
+        if let Some(direction) = board.cycle_shortcut() {
+            let cell = state.neighbour(head, direction).unwrap();
+            assert_eq!(cycle.steps(head, cell), 1);
+        }
+    }
+
+    #[test]
+    fn autopilot_fills_small_board() {
+        for size in [IVec2::new(6, 6), IVec2::new(5, 6)] {
+            let mut state = SimState::new(SimRules {
+                meat_spawn_interval: 1,
+                meat_limit: 1,
+                ..rules(size)
+            });
+            let mut rng = GameRng::new(1);
+            while !state.is_over() && state.tick < 100_000 {
+                let input = autopilot(&state, 0);
+                state.step(&[input], &mut rng);
+            }
+            assert!(state.board_full, "{size}: {:?}", state.snakes[0].death);
+            assert!(state.snakes[0].alive);
+        }
+    }
+}
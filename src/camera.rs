@@ -3,16 +3,19 @@
 
 use bevy::{prelude::*, render::camera::ScalingMode};
 
+use crate::GameConfig;
+
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup);
+        app.init_resource::<GameConfig>()
+            .add_systems(Startup, setup);
     }
 }
 
-fn setup(mut commands: Commands) {
+fn setup(mut commands: Commands, config: Res<GameConfig>) {
     let mut camera_bundle = Camera2dBundle::default();
-    camera_bundle.projection.scaling_mode = ScalingMode::FixedVertical(crate::MAP_SIZE.y);
+    camera_bundle.projection.scaling_mode = ScalingMode::FixedVertical(config.map_height as f32);
     commands.spawn(camera_bundle);
 }
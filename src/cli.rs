@@ -0,0 +1,547 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Command line of `snake-rs` binary
+//!
+//! Every subcommand takes arguments after its name and returns process exit
+//! code. Bad arguments print usage and exit with code 2.
+
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use snake_rs::{
+    bot::BotLevel,
+    config::MAX_PLAYERS,
+    contest::{self, BotCommand, Contestant, StandIn},
+    net::{self, NetClient, NetPeer, Server},
+    replay::{Replay, ReplayError},
+    rollback,
+    sim::SimStats,
+    tournament::{self, Pairing, TournamentOptions, TournamentReport},
+    Autopilot, CameraPlugin, GameConfig, GamePlugin, GameSeed, GameState, MenuPlugin, ReplayPlayer,
+    ReplayRecorder,
+};
+
+/// Command line options
+#[derive(Default)]
+struct Args {
+    /// `--seed <u64>`
+    seed: Option<u64>,
+    /// `--record <file>`
+    record: Option<PathBuf>,
+    /// `--replay <file>`
+    replay: Option<PathBuf>,
+    /// `--config <file>`
+    config: Option<PathBuf>,
+    /// `--players <1-4>`
+    players: Option<usize>,
+    /// `--bots <level,...>`
+    bots: Option<Vec<BotLevel>>,
+    /// `--bind <address>`
+    bind: Option<SocketAddr>,
+    /// `--connect <address>`
+    connect: Option<String>,
+    /// `--host <address>`
+    host: Option<SocketAddr>,
+    /// `--join <address>`
+    join: Option<String>,
+    /// `--delay <ticks>`
+    delay: Option<u64>,
+    /// `--loss <0-1>`
+    loss: Option<f64>,
+    /// `--autopilot`
+    autopilot: bool,
+    /// `--bot <command>`, repeated for every bot program
+    bot_commands: Vec<BotCommand>,
+    /// `--timeout <ms>`
+    timeout: Option<u64>,
+    /// `--pairing <round-robin|swiss>`
+    pairing: Option<Pairing>,
+    /// `--games <n>`
+    games: Option<usize>,
+    /// `--size <2-4>`
+    size: Option<usize>,
+    /// `--jobs <n>`
+    jobs: Option<usize>,
+    /// `--csv <file>`
+    csv: Option<PathBuf>,
+    /// `--json <file>`
+    json: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Self {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            // Support both `--option value` and `--option=value`
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            let mut value = || inline.clone().or_else(|| args.next());
+            match name.as_str() {
+                "--seed" => match value().and_then(|value| value.parse().ok()) {
+                    Some(seed) => parsed.seed = Some(seed),
+                    None => fail("--seed expects unsigned integer"),
+                },
+                "--record" => match value() {
+                    Some(path) => parsed.record = Some(path.into()),
+                    None => fail("--record expects file path"),
+                },
+                "--replay" => match value() {
+                    Some(path) => parsed.replay = Some(path.into()),
+                    None => fail("--replay expects file path"),
+                },
+                "--config" => match value() {
+                    Some(path) => parsed.config = Some(path.into()),
+                    None => fail("--config expects file path"),
+                },
+                "--players" => match value().and_then(|value| value.parse().ok()) {
+                    Some(players @ 1..=MAX_PLAYERS) => parsed.players = Some(players),
+                    _ => fail(&format!("--players expects number from 1 to {MAX_PLAYERS}")),
+                },
+                "--bots" => match value().map(|value| {
+                    value
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<Vec<_>, _>>()
+                }) {
+                    Some(Ok(bots)) => parsed.bots = Some(bots),
+                    Some(Err(err)) => fail(&format!("--bots: {err}")),
+                    None => fail("--bots expects levels: easy, normal or hard"),
+                },
+                "--autopilot" => parsed.autopilot = true,
+                "--bot" => match value() {
+                    Some(command) => parsed.bot_commands.push(bot_command(&command)),
+                    None => fail("--bot expects bot command"),
+                },
+                "--timeout" => match value().and_then(|value| value.parse().ok()) {
+                    Some(timeout) => parsed.timeout = Some(timeout),
+                    None => fail("--timeout expects milliseconds"),
+                },
+                "--pairing" => match value().map(|value| value.parse()) {
+                    Some(Ok(pairing)) => parsed.pairing = Some(pairing),
+                    _ => fail("--pairing expects round-robin or swiss"),
+                },
+                "--games" => match value().and_then(|value| value.parse().ok()) {
+                    Some(games @ 1..) => parsed.games = Some(games),
+                    _ => fail("--games expects positive number"),
+                },
+                "--size" => match value().and_then(|value| value.parse().ok()) {
+                    Some(size @ 2..=MAX_PLAYERS) => parsed.size = Some(size),
+                    _ => fail(&format!("--size expects number from 2 to {MAX_PLAYERS}")),
+                },
+                "--jobs" => match value().and_then(|value| value.parse().ok()) {
+                    Some(jobs @ 1..) => parsed.jobs = Some(jobs),
+                    _ => fail("--jobs expects positive number"),
+                },
+                "--csv" => match value() {
+                    Some(path) => parsed.csv = Some(path.into()),
+                    None => fail("--csv expects file path"),
+                },
+                "--json" => match value() {
+                    Some(path) => parsed.json = Some(path.into()),
+                    None => fail("--json expects file path"),
+                },
+                "--bind" => match value().and_then(|value| value.parse().ok()) {
+                    Some(addr) => parsed.bind = Some(addr),
+                    None => fail("--bind expects socket address"),
+                },
+                "--connect" => match value() {
+                    Some(addr) => parsed.connect = Some(addr),
+                    None => fail("--connect expects server address"),
+                },
+                "--host" => match value().and_then(|value| value.parse().ok()) {
+                    Some(addr) => parsed.host = Some(addr),
+                    None => fail("--host expects socket address"),
+                },
+                "--join" => match value() {
+                    Some(addr) => parsed.join = Some(addr),
+                    None => fail("--join expects peer address"),
+                },
+                "--delay" => match value().and_then(|value| value.parse().ok()) {
+                    Some(delay) => parsed.delay = Some(delay),
+                    None => fail("--delay expects number of ticks"),
+                },
+                "--loss" => match value().and_then(|value| value.parse().ok()) {
+                    Some(loss @ 0.0..=1.0) => parsed.loss = Some(loss),
+                    _ => fail("--loss expects number from 0 to 1"),
+                },
+                _ => fail(&format!("unknown argument {arg}")),
+            }
+        }
+        parsed
+    }
+}
+
+/// Bot program, `stand-in:<behaviour>` runs this executable as bot
+fn bot_command(command: &str) -> BotCommand {
+    if let Some(behaviour) = command.strip_prefix("stand-in:") {
+        let program =
+            std::env::current_exe().unwrap_or_else(|err| fail(&format!("stand-in bot: {err}")));
+        return BotCommand {
+            name: command.to_string(),
+            program: program.to_string_lossy().into_owned(),
+            args: vec!["bot".to_string(), behaviour.to_string()],
+        };
+    }
+    BotCommand::parse(command).unwrap_or_else(|| fail("--bot expects bot command"))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(2);
+}
+
+/// Config when no config file passed
+#[cfg(not(target_arch = "wasm32"))]
+fn default_config() -> GameConfig {
+    GameConfig::default()
+}
+
+/// Config from URL query parameters of the page
+#[cfg(target_arch = "wasm32")]
+fn default_config() -> GameConfig {
+    GameConfig::from_url().unwrap_or_else(|err| {
+        web_sys::console::warn_1(&err.to_string().into());
+        GameConfig::default()
+    })
+}
+
+/// Config from `--config`, `--players` and `--bots` options
+fn load_config(args: &Args) -> GameConfig {
+    let config = read_config(args);
+    if let Err(err) = config.validate() {
+        fail(&err.to_string());
+    }
+    config
+}
+
+/// Config from options without validation, players are set later
+fn read_config(args: &Args) -> GameConfig {
+    let mut config = match &args.config {
+        Some(path) => {
+            GameConfig::load(path).unwrap_or_else(|err| fail(&format!("{}: {err}", path.display())))
+        }
+        None => default_config(),
+    };
+    if let Some(players) = args.players {
+        config.players = players;
+    }
+    if let Some(bots) = &args.bots {
+        config.bots = bots.clone();
+    }
+    config
+}
+
+/// Bot programs of `--bot`, then built-in bots of config
+fn contestants(args: &Args, config: &GameConfig) -> Vec<Contestant> {
+    args.bot_commands
+        .iter()
+        .cloned()
+        .map(Contestant::Program)
+        .chain(config.bots.iter().copied().map(Contestant::Builtin))
+        .collect()
+}
+
+/// Print statistics of every snake
+fn print_stats(stats: &[SimStats]) {
+    for (index, stats) in stats.iter().enumerate() {
+        println!(
+            "player {}: score: {}, food eaten: {}, distance traveled: {}",
+            index + 1,
+            stats.score,
+            stats.food_eaten,
+            stats.distance_traveled
+        );
+    }
+}
+
+/// `verify <replay>`: simulate replay without rendering and compare final statistics
+///
+/// Replay must use rules of default or `--config` config, only players & bots
+/// may differ. Returns process exit code
+pub fn verify(mut args: impl Iterator<Item = String>) -> i32 {
+    let Some(path) = args.next().filter(|path| !path.starts_with("--")) else {
+        fail("usage: snake-rs verify <replay> [--config <file>]");
+    };
+    let expected = &load_config(&Args::parse(args));
+    let path = Path::new(&path);
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(ReplayError::Config(err)) => {
+            println!("MISMATCH: replay rules are invalid: {err}");
+            return 1;
+        }
+        Err(err) => {
+            eprintln!("{}: {err}", path.display());
+            return 2;
+        }
+    };
+
+    println!("seed: {}", replay.seed);
+    println!("rules: {}", replay.config.rules());
+    println!("ticks: {}", replay.ticks.len());
+    let changes = replay.rule_changes(expected);
+    if !changes.is_empty() {
+        println!("MISMATCH: replay changes rules");
+        for change in changes {
+            println!("{change}");
+        }
+        return 1;
+    }
+
+    let stats = replay.simulate().stats();
+    print_stats(&stats);
+
+    match replay.stats {
+        Some(expected) if expected == stats => {
+            println!("OK");
+            0
+        }
+        Some(expected) => {
+            println!("MISMATCH: replay claims");
+            print_stats(&expected);
+            1
+        }
+        None => {
+            println!("MISMATCH: replay has no final statistics");
+            1
+        }
+    }
+}
+
+/// `loopback`: play peer-to-peer game with random inputs over delayed loopback
+///
+/// Returns process exit code
+pub fn loopback(args: impl Iterator<Item = String>) -> i32 {
+    let args = &Args::parse(args);
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let delay = args.delay.unwrap_or(3);
+    let loss = args.loss.unwrap_or(0.);
+    let report = rollback::loopback(&load_config(args), seed, delay, loss, 10_000);
+    println!("seed: {seed}");
+    println!("delay: {delay} ticks, loss: {loss}");
+    println!("ticks: {}", report.ticks);
+    println!("rollbacks: {:?}", report.rollbacks);
+    if report.agree && report.replay_agrees {
+        println!("OK");
+        0
+    } else {
+        println!(
+            "MISMATCH: peers agree: {}, replay agrees: {}",
+            report.agree, report.replay_agrees
+        );
+        1
+    }
+}
+
+/// `contest`: play game of bot programs and print results
+///
+/// Returns process exit code
+pub fn contest(args: impl Iterator<Item = String>) -> i32 {
+    let args = &Args::parse(args);
+    let mut config = read_config(args);
+    let contestants = contestants(args, &config);
+    if contestants.is_empty() {
+        fail("usage: snake-rs contest --bot <command> [--bot <command>...] [--bots <level,...>]");
+    }
+    config.players = contestants.len();
+    config.bots.clear();
+    if let Err(err) = config.validate() {
+        fail(&err.to_string());
+    }
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let timeout = Duration::from_millis(args.timeout.unwrap_or(100));
+    let report = match contest::run_contest(&config, &contestants, seed, timeout, 100_000) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("contest: {err}");
+            return 2;
+        }
+    };
+
+    contest::print_results(&report);
+    0
+}
+
+/// `tournament`: play tournament of bot programs & built-in bots, print ranking
+///
+/// Returns process exit code
+pub fn tournament(args: impl Iterator<Item = String>) -> i32 {
+    let args = &Args::parse(args);
+    let mut config = read_config(args);
+    let entrants = contestants(args, &config);
+    let defaults = TournamentOptions::default();
+    let options = TournamentOptions {
+        pairing: args.pairing.unwrap_or_default(),
+        games: args.games.unwrap_or(defaults.games),
+        size: args.size.unwrap_or(defaults.size),
+        jobs: args.jobs.unwrap_or(defaults.jobs),
+        seed: args.seed.unwrap_or_else(rand::random),
+        move_timeout: args
+            .timeout
+            .map_or(defaults.move_timeout, Duration::from_millis),
+        ..defaults
+    };
+    if entrants.len() < options.size {
+        fail(&format!(
+            "tournament needs at least {} entrants (--bot <command>, --bots <level,...>)",
+            options.size
+        ));
+    }
+    config.players = options.size;
+    config.bots.clear();
+    if let Err(err) = config.validate() {
+        fail(&err.to_string());
+    }
+
+    let report = match tournament::run_tournament(&config, &entrants, &options) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("tournament: {err}");
+            return 2;
+        }
+    };
+    println!("seed: {}", options.seed);
+    println!(
+        "pairing: {}, games: {}",
+        options.pairing,
+        report.games.len()
+    );
+    tournament::print_ranking(&report);
+
+    let saved = [
+        (&args.csv, TournamentReport::to_csv as fn(&_) -> _),
+        (&args.json, TournamentReport::to_json),
+    ]
+    .into_iter()
+    .filter_map(|(path, format)| Some((path.as_ref()?, format)))
+    .try_for_each(|(path, format)| {
+        std::fs::write(path, format(&report)).map_err(|err| format!("{}: {err}", path.display()))
+    });
+    if let Err(err) = saved {
+        eprintln!("{err}");
+        return 2;
+    }
+    0
+}
+
+/// `server`: run game server till it fails
+///
+/// Returns process exit code
+pub fn server(args: impl Iterator<Item = String>) -> i32 {
+    let args = Args::parse(args);
+    let bind = args
+        .bind
+        .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], net::DEFAULT_PORT)));
+    let config = load_config(&args);
+    let players = config.players;
+    let result = Server::bind(bind, config, args.seed).and_then(|server| {
+        println!("Waiting for {players} players on {}", server.local_addr()?);
+        // Server reports players & games to log
+        bevy::log::tracing_subscriber::fmt().init();
+        server.run()
+    });
+    if let Err(err) = result {
+        fail(&format!("server: {err}"));
+    }
+    0
+}
+
+/// `bot <behaviour>`: play as stand-in bot over stdin & stdout
+///
+/// Returns process exit code
+pub fn bot(mut args: impl Iterator<Item = String>) -> i32 {
+    let (Some(behaviour), None) = (args.next(), args.next()) else {
+        fail("usage: snake-rs bot <easy|normal|hard|moves:up,keep,...|sleep:<ms>|garbage|quit>");
+    };
+    let stand_in: StandIn = behaviour.parse().unwrap_or_else(|err: String| fail(&err));
+    if let Err(err) = contest::run_stand_in(&stand_in, io::stdin().lock(), io::stdout().lock()) {
+        fail(&format!("bot: {err}"));
+    }
+    0
+}
+
+/// Play the game in window
+pub fn play(args: impl Iterator<Item = String>) {
+    let args = Args::parse(args);
+    let mut config = load_config(&args);
+
+    let mut app = App::new();
+    app.insert_resource(GameSeed(args.seed));
+    app.insert_resource(Autopilot(args.autopilot));
+    if let Some(path) = args.record {
+        app.insert_resource(ReplayRecorder::to_file(path));
+    }
+    if let Some(path) = args.replay {
+        match Replay::load(&path) {
+            Ok(replay) => {
+                // Replay brings own rules
+                config = replay.config.clone();
+                app.insert_resource(ReplayPlayer::new(replay));
+            }
+            Err(err) => fail(&format!("{}: {err}", path.display())),
+        };
+    }
+    if let Some(addr) = &args.connect {
+        // Server brings own rules
+        match NetClient::connect(addr.as_str()) {
+            Ok((client, server_config)) => {
+                config = server_config;
+                app.insert_resource(client);
+            }
+            Err(err) => fail(&format!("{addr}: {err}")),
+        }
+    }
+    if let Some(addr) = args.host {
+        // Peer-to-peer game is always for two players
+        config.players = 2;
+        config.bots.clear();
+        let seed = args.seed.unwrap_or_else(rand::random);
+        println!("Waiting for peer on {addr}");
+        match NetPeer::host(addr, config.clone(), seed) {
+            Ok(peer) => {
+                app.insert_resource(peer);
+            }
+            Err(err) => fail(&format!("{addr}: {err}")),
+        }
+    }
+    if let Some(addr) = &args.join {
+        // Host brings own rules
+        match NetPeer::join(addr.as_str()) {
+            Ok((peer, host_config)) => {
+                config = host_config;
+                app.insert_resource(peer);
+            }
+            Err(err) => fail(&format!("{addr}: {err}")),
+        }
+    }
+    app.insert_resource(config);
+
+    app.insert_state(GameState::MainMenu)
+        .add_plugins((
+            DefaultPlugins.set(bevy::window::WindowPlugin {
+                primary_window: Some(bevy::window::Window {
+                    prevent_default_event_handling: true,
+                    canvas: Some("#snake-rs-canvas".into()),
+                    name: Some("snake-rs.app".into()),
+                    title: "snake-rs".into(),
+                    ..default()
+                }),
+                ..default()
+            }), // .set(bevy::log::LogPlugin {
+            //     level: bevy::log::Level::DEBUG,
+            //     ..default()
+            // })
+            CameraPlugin,
+            GamePlugin,
+            MenuPlugin,
+        ))
+        .run();
+}
@@ -0,0 +1,271 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Game rules configuration
+//!
+//! Loaded from RON file on desktop and from URL query parameters on web.
+
+use std::{fmt, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bot::BotLevel,
+    sim::{BorderMode, SimRules},
+};
+
+/// Maximum count of snakes (local players & bots)
+pub const MAX_PLAYERS: usize = 4;
+
+/// Maximum board width & height in cells
+pub const MAX_MAP_SIZE: i32 = 1000;
+
+/// Tunable game rules
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// Board width in cells
+    pub map_width: i32,
+    /// Board height in cells
+    pub map_height: i32,
+    /// Seconds between game ticks
+    pub tick_seconds: f64,
+    /// Tick speed multiplier while speed up is requested
+    pub speed_up: f64,
+    /// Seconds between meat spawns
+    pub meat_spawn_seconds: f64,
+    /// Maximum count of meat on the board
+    pub meat_limit: usize,
+    /// Score for one eaten meat
+    pub meat_score: u32,
+    /// Steps of snake fat animation after eating meat
+    pub fat_steps: i32,
+    /// Wrap around or solid map edges
+    pub border_mode: BorderMode,
+    /// Count of local players
+    pub players: usize,
+    /// Computer-controlled snakes after players
+    pub bots: Vec<BotLevel>,
+    /// Seconds without meat before snake starves, 0 disables starvation
+    pub starvation_seconds: f64,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            map_width: 80,
+            map_height: 50,
+            tick_seconds: 0.2,
+            speed_up: 2.,
+            meat_spawn_seconds: 3.,
+            meat_limit: 9,
+            meat_score: 50,
+            fat_steps: 5,
+            border_mode: BorderMode::Wrap,
+            players: 1,
+            bots: Vec::new(),
+            starvation_seconds: 0.,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Board size in cells
+    pub fn map_size(&self) -> IVec2 {
+        IVec2::new(self.map_width, self.map_height)
+    }
+
+    /// Level of bot playing snake, `None` for player
+    pub fn bot(&self, index: usize) -> Option<BotLevel> {
+        index
+            .checked_sub(self.players)
+            .and_then(|bot| self.bots.get(bot))
+            .copied()
+    }
+
+    /// Rules for simulation
+    pub fn rules(&self) -> SimRules {
+        SimRules {
+            map_size: self.map_size(),
+            meat_spawn_interval: (self.meat_spawn_seconds / self.tick_seconds)
+                .round()
+                .max(1.) as u64,
+            meat_limit: self.meat_limit,
+            meat_score: self.meat_score,
+            border_mode: self.border_mode,
+            snakes: self.players + self.bots.len(),
+            starvation_ticks: if self.starvation_seconds > 0. {
+                (self.starvation_seconds / self.tick_seconds)
+                    .round()
+                    .max(1.) as u64
+            } else {
+                0
+            },
+            players: self.players,
+        }
+    }
+
+    /// Read config from RON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let data = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let config: GameConfig = ron::from_str(&data).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Apply URL query parameters (`?map_width=40&tick_seconds=0.1`)
+    pub fn apply_query(&mut self, query: &str) -> Result<(), ConfigError> {
+        for pair in query.trim_start_matches('?').split('&') {
+            let Some((key, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let invalid = || ConfigError::Value(key.into(), value.into());
+            match key {
+                "map_width" => self.map_width = value.parse().map_err(|_| invalid())?,
+                "map_height" => self.map_height = value.parse().map_err(|_| invalid())?,
+                "tick_seconds" => self.tick_seconds = value.parse().map_err(|_| invalid())?,
+                "speed_up" => self.speed_up = value.parse().map_err(|_| invalid())?,
+                "meat_spawn_seconds" => {
+                    self.meat_spawn_seconds = value.parse().map_err(|_| invalid())?
+                }
+                "meat_limit" => self.meat_limit = value.parse().map_err(|_| invalid())?,
+                "meat_score" => self.meat_score = value.parse().map_err(|_| invalid())?,
+                "fat_steps" => self.fat_steps = value.parse().map_err(|_| invalid())?,
+                "players" => self.players = value.parse().map_err(|_| invalid())?,
+                "bots" => {
+                    self.bots = value
+                        .split(',')
+                        .filter(|level| !level.is_empty())
+                        .map(|level| level.parse().map_err(|_| invalid()))
+                        .collect::<Result<_, _>>()?
+                }
+                "starvation_seconds" => {
+                    self.starvation_seconds = value.parse().map_err(|_| invalid())?
+                }
+                "border_mode" => self.border_mode = value.parse().map_err(|_| invalid())?,
+                // Not a config parameter
+                _ => continue,
+            }
+        }
+        self.validate()
+    }
+
+    /// Read config from URL query parameters of the page
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_url() -> Result<Self, ConfigError> {
+        let mut config = GameConfig::default();
+        if let Some(query) = web_sys::window().and_then(|window| window.location().search().ok()) {
+            config.apply_query(&query)?;
+        }
+        Ok(config)
+    }
+
+    /// Check values are playable
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.map_width < 4 || self.map_height < 4 {
+            return Err(ConfigError::Invalid("map must be at least 4x4"));
+        }
+        if self.map_width > MAX_MAP_SIZE || self.map_height > MAX_MAP_SIZE {
+            return Err(ConfigError::Invalid("map must be 1000x1000 at most"));
+        }
+        let positive = |value: f64| value.is_finite() && value > 0.;
+        if !positive(self.tick_seconds) || !positive(self.speed_up) {
+            return Err(ConfigError::Invalid(
+                "tick_seconds & speed_up must be positive",
+            ));
+        }
+        if !positive(self.meat_spawn_seconds) {
+            return Err(ConfigError::Invalid("meat_spawn_seconds must be positive"));
+        }
+        if !(1..=MAX_PLAYERS).contains(&self.players) {
+            return Err(ConfigError::Invalid("players must be from 1 to 4"));
+        }
+        if self.players + self.bots.len() > MAX_PLAYERS {
+            return Err(ConfigError::Invalid("players & bots must be 4 at most"));
+        }
+        if !self.starvation_seconds.is_finite() || self.starvation_seconds < 0. {
+            return Err(ConfigError::Invalid(
+                "starvation_seconds can not be negative",
+            ));
+        }
+        if self.fat_steps < 1 {
+            return Err(ConfigError::Invalid("fat_steps must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+/// Failed to load config
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    /// Parameter has wrong value
+    Value(String, String),
+    /// Values can not be used together
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "config io error: {err}"),
+            ConfigError::Parse(err) => write!(f, "config parse error: {err}"),
+            ConfigError::Value(key, value) => write!(f, "wrong value of {key}: {value}"),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_sets_values() {
+        let mut config = GameConfig::default();
+        config
+            .apply_query("?map_width=40&tick_seconds=0.1&bots=easy,hard&border_mode=solid&page=2")
+            .unwrap();
+        assert_eq!(config.map_width, 40);
+        assert_eq!(config.tick_seconds, 0.1);
+        assert_eq!(config.bots, [BotLevel::Easy, BotLevel::Hard]);
+        assert_eq!(config.border_mode, BorderMode::Solid);
+        assert_eq!(config.map_height, GameConfig::default().map_height);
+    }
+
+    #[test]
+    fn query_rejects_not_finite_times() {
+        for query in [
+            "tick_seconds=NaN",
+            "tick_seconds=inf",
+            "speed_up=inf",
+            "speed_up=-inf",
+            "meat_spawn_seconds=NaN",
+            "starvation_seconds=inf",
+        ] {
+            let result = GameConfig::default().apply_query(query);
+            assert!(
+                matches!(result, Err(ConfigError::Invalid(_))),
+                "{query} accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn query_rejects_oversized_map() {
+        let result = GameConfig::default().apply_query("map_width=100000&map_height=100000");
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+        let result = GameConfig::default().apply_query("map_height=3");
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn query_rejects_wrong_values() {
+        let result = GameConfig::default().apply_query("map_width=wide");
+        assert!(matches!(result, Err(ConfigError::Value(..))));
+    }
+}
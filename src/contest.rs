@@ -0,0 +1,845 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Games of external bot programs
+//!
+//! Every bot is a child process talking JSON lines over stdin/stdout. Bot
+//! gets [`BotMessage::Start`] once, then [`BotMessage::Tick`] before every
+//! tick and answers each tick with one line `{"direction": "up"}` (or `null`
+//! to keep direction). Up is +y. Bot which is late, answers nonsense or
+//! exits is disqualified. Stderr of bots is passed through for debugging.
+//! Built-in bots may play in the same game.
+
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, Command, Stdio},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::math::IVec2;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    bot::{self, BotLevel},
+    sim::{
+        BorderMode, DeathCause, SimMeat, SimRules, SimSnake, SimState, SimStats, SnakeDirection,
+    },
+    GameConfig, GameRng,
+};
+
+/// Extra time for the first answer, bot may be still starting
+pub const STARTUP_TIME: Duration = Duration::from_secs(2);
+
+/// Message for bot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    /// Game started
+    Start {
+        /// Index of bot snake
+        you: usize,
+        map_size: IVec2,
+        #[serde(serialize_with = "display", deserialize_with = "parse")]
+        border_mode: BorderMode,
+        /// Time for one answer
+        move_timeout_ms: u64,
+    },
+    /// Board before tick, bot answers with direction
+    Tick {
+        tick: u64,
+        you: usize,
+        map_size: IVec2,
+        snakes: Vec<BoardSnake>,
+        /// Meat positions
+        meats: Vec<IVec2>,
+    },
+    /// Game is over, no answer expected
+    End {
+        tick: u64,
+        you: usize,
+        alive: bool,
+        score: u32,
+    },
+}
+
+/// Snake in tick message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardSnake {
+    pub alive: bool,
+    #[serde(serialize_with = "display", deserialize_with = "parse")]
+    pub direction: SnakeDirection,
+    /// Head first
+    pub body: Vec<IVec2>,
+}
+
+/// Answer of bot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BotAnswer {
+    /// New direction, `None` keeps direction
+    pub direction: Option<String>,
+}
+
+fn display<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+fn parse<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr<Err = String>,
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+/// Why bot was removed from the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Disqualification {
+    /// Bot did not answer in time
+    Timeout,
+    /// Answer is not a direction
+    InvalidAnswer(String),
+    /// Bot answered without being asked
+    UnexpectedAnswer(String),
+    /// Bot closed its output or could not be written to
+    Exited,
+}
+
+impl fmt::Display for Disqualification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Disqualification::Timeout => write!(f, "timeout"),
+            Disqualification::InvalidAnswer(line) => write!(f, "invalid answer {line:?}"),
+            Disqualification::UnexpectedAnswer(line) => write!(f, "unexpected answer {line:?}"),
+            Disqualification::Exited => write!(f, "exited"),
+        }
+    }
+}
+
+/// Program playing one snake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotCommand {
+    /// Name in results
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl BotCommand {
+    /// Command from whitespace separated program & arguments
+    pub fn parse(command: &str) -> Option<Self> {
+        let mut words = command.split_whitespace().map(String::from);
+        Some(Self {
+            name: command.to_string(),
+            program: words.next()?,
+            args: words.collect(),
+        })
+    }
+}
+
+/// Running bot program
+struct BotProcess {
+    /// Program behind the pipes, killed on drop
+    child: Option<Child>,
+    /// Lines for the bot, written in background
+    messages: Sender<String>,
+    /// Result of every write in order of messages
+    written: Receiver<io::Result<()>>,
+    /// Messages not written yet
+    pending: usize,
+    lines: Receiver<String>,
+}
+
+impl BotProcess {
+    fn spawn(command: &BotCommand) -> io::Result<Self> {
+        let mut child = Command::new(&command.program)
+            .args(&command.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(Self::connect(Some(child), stdin, stdout))
+    }
+
+    /// Bot talking over `stdin` & `stdout`
+    fn connect(
+        child: Option<Child>,
+        mut stdin: impl Write + Send + 'static,
+        stdout: impl Read + Send + 'static,
+    ) -> Self {
+        // Bot which does not read input blocks writes, not the game
+        let (messages, queue) = mpsc::channel::<String>();
+        let (wrote, written) = mpsc::channel();
+        thread::spawn(move || {
+            for line in queue {
+                let result = writeln!(stdin, "{line}").and_then(|_| stdin.flush());
+                let failed = result.is_err();
+                if wrote.send(result).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        // Lines are read in background, so answers can wait with timeout
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            child,
+            messages,
+            written,
+            pending: 0,
+            lines,
+        }
+    }
+
+    fn send(&mut self, message: &BotMessage) -> Result<(), Disqualification> {
+        let line = serde_json::to_string(message).expect("message is serializable");
+        self.messages
+            .send(line)
+            .map_err(|_| Disqualification::Exited)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Wait till bot reads every sent message
+    fn delivered(&mut self, deadline: Instant) -> Result<(), Disqualification> {
+        while self.pending > 0 {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.written.recv_timeout(timeout) {
+                Ok(Ok(())) => self.pending -= 1,
+                Err(RecvTimeoutError::Timeout) => return Err(Disqualification::Timeout),
+                Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Disqualification::Exited)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bot must stay silent until asked
+    fn check_silent(&self) -> Result<(), Disqualification> {
+        match self.lines.try_recv() {
+            Ok(line) => Err(Disqualification::UnexpectedAnswer(line)),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn answer(&mut self, deadline: Instant) -> Result<Option<SnakeDirection>, Disqualification> {
+        self.delivered(deadline)?;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let line = match self.lines.recv_timeout(timeout) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => return Err(Disqualification::Timeout),
+            Err(RecvTimeoutError::Disconnected) => return Err(Disqualification::Exited),
+        };
+        let invalid = || Disqualification::InvalidAnswer(line.clone());
+        let answer: BotAnswer = serde_json::from_str(&line).map_err(|_| invalid())?;
+        answer
+            .direction
+            .map(|direction| direction.parse().map_err(|_| invalid()))
+            .transpose()
+    }
+}
+
+impl Drop for BotProcess {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Player of one snake in contest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Contestant {
+    /// External bot program
+    Program(BotCommand),
+    /// Built-in bot, played in process
+    Builtin(BotLevel),
+}
+
+impl Contestant {
+    /// Name in results
+    pub fn name(&self) -> String {
+        match self {
+            Contestant::Program(command) => command.name.clone(),
+            Contestant::Builtin(level) => format!("{level} bot"),
+        }
+    }
+}
+
+/// Result of one snake in contest
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContestSnake {
+    pub name: String,
+    pub stats: SimStats,
+    pub alive: bool,
+    /// Ticks played before death or end of the game
+    pub ticks_alive: u64,
+    pub death: Option<DeathCause>,
+    pub disqualified: Option<Disqualification>,
+}
+
+/// Result of contest game
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContestReport {
+    pub seed: u64,
+    pub ticks: u64,
+    pub snakes: Vec<ContestSnake>,
+}
+
+/// Print result of every snake
+pub fn print_results(report: &ContestReport) {
+    println!("seed: {}", report.seed);
+    println!("ticks: {}", report.ticks);
+    for (index, snake) in report.snakes.iter().enumerate() {
+        let result = match (&snake.disqualified, snake.death) {
+            (Some(reason), _) => format!("disqualified: {reason}"),
+            (None, Some(death)) => format!("died: {death}"),
+            (None, None) => "alive".to_string(),
+        };
+        println!(
+            "snake {} ({}): score: {}, food eaten: {}, kills: {}, {result}",
+            index + 1,
+            snake.name,
+            snake.stats.score,
+            snake.stats.food_eaten,
+            snake.stats.kills,
+        );
+    }
+}
+
+/// Play game of contestants, snake index is index of contestant
+///
+/// Players & bots of `config` are ignored. Game stops when it is over or
+/// after `max_ticks`.
+pub fn run_contest(
+    config: &GameConfig,
+    contestants: &[Contestant],
+    seed: u64,
+    move_timeout: Duration,
+    max_ticks: u64,
+) -> io::Result<ContestReport> {
+    let processes = contestants
+        .iter()
+        .map(|contestant| match contestant {
+            Contestant::Program(command) => BotProcess::spawn(command).map(Some),
+            Contestant::Builtin(_) => Ok(None),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(play(
+        config,
+        contestants,
+        processes,
+        seed,
+        move_timeout,
+        max_ticks,
+    ))
+}
+
+/// Play game with running bot of every program contestant
+fn play(
+    config: &GameConfig,
+    contestants: &[Contestant],
+    mut processes: Vec<Option<BotProcess>>,
+    seed: u64,
+    move_timeout: Duration,
+    max_ticks: u64,
+) -> ContestReport {
+    let config = GameConfig {
+        players: contestants.len(),
+        bots: Vec::new(),
+        ..config.clone()
+    };
+    let mut state = SimState::new(config.rules());
+    let mut rng = GameRng::new(seed);
+    let mut disqualified = vec![None; contestants.len()];
+    let mut ticks_alive = vec![0; contestants.len()];
+
+    for (you, process) in processes.iter_mut().enumerate() {
+        let message = BotMessage::Start {
+            you,
+            map_size: state.rules.map_size,
+            border_mode: state.rules.border_mode,
+            move_timeout_ms: move_timeout.as_millis() as u64,
+        };
+        if let Some(Err(reason)) = process.as_mut().map(|process| process.send(&message)) {
+            disqualified[you] = Some(reason);
+        }
+    }
+
+    while !state.is_over() && state.tick < max_ticks {
+        // Ask all bots first, so they think at the same time
+        for (you, process) in processes.iter_mut().enumerate() {
+            if disqualified[you].is_some() || !state.snakes[you].alive {
+                continue;
+            }
+            let Some(process) = process else {
+                continue;
+            };
+            let message = tick_message(&state, you);
+            if let Err(reason) = process.check_silent().and_then(|_| process.send(&message)) {
+                disqualified[you] = Some(reason);
+            }
+        }
+        let mut deadline = Instant::now() + move_timeout;
+        if state.tick == 0 {
+            deadline += STARTUP_TIME;
+        }
+
+        let mut inputs = Vec::with_capacity(state.snakes.len());
+        for (you, process) in processes.iter_mut().enumerate() {
+            if !state.snakes[you].alive {
+                inputs.push(None);
+                continue;
+            }
+            let input = match (&contestants[you], process.as_mut()) {
+                (Contestant::Builtin(level), _) => Ok(bot::think(&state, you, *level)),
+                (Contestant::Program(_), Some(process)) if disqualified[you].is_none() => {
+                    process.answer(deadline)
+                }
+                (Contestant::Program(_), _) => Ok(None),
+            };
+            if let Err(reason) = input.as_ref() {
+                disqualified[you] = Some(reason.clone());
+            }
+            if disqualified[you].is_some() {
+                state.disqualify(you);
+                *process = None;
+            }
+            inputs.push(input.ok().flatten());
+        }
+        state.step(&inputs, &mut rng);
+
+        for (snake, ticks) in state.snakes.iter().zip(&mut ticks_alive) {
+            if snake.alive {
+                *ticks = state.tick;
+            }
+        }
+    }
+
+    for (you, process) in processes.iter_mut().enumerate() {
+        let message = BotMessage::End {
+            tick: state.tick,
+            you,
+            alive: state.snakes[you].alive,
+            score: state.snakes[you].stats.score,
+        };
+        if let Some(process) = process {
+            // Bot may be already gone, game is over anyway
+            let deadline = Instant::now() + move_timeout;
+            let _ = process
+                .send(&message)
+                .and_then(|_| process.delivered(deadline));
+        }
+    }
+
+    ContestReport {
+        seed,
+        ticks: state.tick,
+        snakes: state
+            .snakes
+            .iter()
+            .zip(contestants)
+            .zip(ticks_alive.into_iter().zip(disqualified))
+            .map(
+                |((snake, contestant), (ticks_alive, disqualified))| ContestSnake {
+                    name: contestant.name(),
+                    stats: snake.stats,
+                    alive: snake.alive,
+                    ticks_alive,
+                    death: snake.death,
+                    disqualified,
+                },
+            )
+            .collect(),
+    }
+}
+
+/// Board before the next tick for bot `you`
+fn tick_message(state: &SimState, you: usize) -> BotMessage {
+    BotMessage::Tick {
+        tick: state.tick,
+        you,
+        map_size: state.rules.map_size,
+        snakes: state
+            .snakes
+            .iter()
+            .map(|snake| BoardSnake {
+                alive: snake.alive,
+                direction: snake.direction,
+                body: snake.body.iter().copied().collect(),
+            })
+            .collect(),
+        meats: state.meats.iter().map(|meat| meat.position).collect(),
+    }
+}
+
+/// Behaviour of stand-in bot program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StandIn {
+    /// Plays like built-in bot
+    Level(BotLevel),
+    /// Repeats moves, `None` keeps direction
+    Moves(Vec<Option<SnakeDirection>>),
+    /// Plays like hard bot, but sleeps before every answer
+    Sleep(Duration),
+    /// Answers nonsense
+    Garbage,
+    /// Exits on the first tick
+    Quit,
+}
+
+impl FromStr for StandIn {
+    type Err = String;
+
+    /// `easy`, `normal`, `hard`, `moves:up,keep,left`, `sleep:<ms>`,
+    /// `garbage` or `quit`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(moves) = value.strip_prefix("moves:") {
+            return moves
+                .split(',')
+                .map(|word| match word {
+                    "keep" => Ok(None),
+                    word => word.parse().map(Some),
+                })
+                .collect::<Result<_, _>>()
+                .map(StandIn::Moves);
+        }
+        if let Some(millis) = value.strip_prefix("sleep:") {
+            return millis
+                .parse()
+                .map(|millis| StandIn::Sleep(Duration::from_millis(millis)))
+                .map_err(|_| format!("sleep expects milliseconds, got {millis}"));
+        }
+        match value {
+            "garbage" => Ok(StandIn::Garbage),
+            "quit" => Ok(StandIn::Quit),
+            level => level
+                .parse()
+                .map(StandIn::Level)
+                .map_err(|_| format!("unknown stand-in bot {value}")),
+        }
+    }
+}
+
+/// Play as bot program over `input` & `output` until game ends
+///
+/// Stand-ins follow the protocol like external bots, so contests can be
+/// tried without bots in other languages.
+pub fn run_stand_in(
+    stand_in: &StandIn,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    let mut border_mode = BorderMode::default();
+    let mut moves = 0;
+    for line in input.lines() {
+        let message: BotMessage = serde_json::from_str(&line?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let (state, you) = match message {
+            BotMessage::Start {
+                border_mode: mode, ..
+            } => {
+                border_mode = mode;
+                continue;
+            }
+            BotMessage::End { .. } => break,
+            BotMessage::Tick {
+                tick,
+                you,
+                map_size,
+                snakes,
+                meats,
+            } => (board_state(tick, map_size, border_mode, snakes, meats), you),
+        };
+
+        let direction = match stand_in {
+            StandIn::Level(level) => bot::think(&state, you, *level),
+            StandIn::Moves(list) => {
+                let direction = list[moves % list.len()];
+                moves += 1;
+                direction
+            }
+            StandIn::Sleep(time) => {
+                thread::sleep(*time);
+                bot::think(&state, you, BotLevel::Hard)
+            }
+            StandIn::Garbage => {
+                writeln!(output, "garbage")?;
+                output.flush()?;
+                continue;
+            }
+            StandIn::Quit => return Ok(()),
+        };
+        let answer = BotAnswer {
+            direction: direction.map(|direction| direction.to_string()),
+        };
+        let line = serde_json::to_string(&answer).expect("answer is serializable");
+        writeln!(output, "{line}")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Game rebuilt from tick message, enough for built-in bots
+fn board_state(
+    tick: u64,
+    map_size: IVec2,
+    border_mode: BorderMode,
+    snakes: Vec<BoardSnake>,
+    meats: Vec<IVec2>,
+) -> SimState {
+    let mut state = SimState::new(SimRules {
+        map_size,
+        border_mode,
+        snakes: snakes.len(),
+        ..SimRules::default()
+    });
+    state.tick = tick;
+    state.snakes = snakes
+        .into_iter()
+        .map(|snake| SimSnake {
+            direction: snake.direction,
+            body: snake.body.into(),
+            alive: snake.alive,
+            stats: SimStats::default(),
+            hunger: 0,
+            death: None,
+            killer: None,
+        })
+        .collect();
+    state.meats = meats
+        .into_iter()
+        .zip(0..)
+        .map(|(position, id)| SimMeat {
+            id,
+            position,
+            hue: 0.,
+        })
+        .collect();
+    state.rebuild_grid();
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writing end of in-memory pipe, closed on drop
+    struct PipeWriter(Sender<Vec<u8>>);
+
+    impl Write for PipeWriter {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0
+                .send(data.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reading end of in-memory pipe
+    struct PipeReader {
+        chunks: Receiver<Vec<u8>>,
+        chunk: io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for PipeReader {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            while self.chunk.position() as usize == self.chunk.get_ref().len() {
+                match self.chunks.recv() {
+                    Ok(chunk) => self.chunk = io::Cursor::new(chunk),
+                    // Writer is gone
+                    Err(_) => return Ok(0),
+                }
+            }
+            self.chunk.read(buffer)
+        }
+    }
+
+    fn pipe() -> (PipeWriter, PipeReader) {
+        let (sender, chunks) = mpsc::channel();
+        let reader = PipeReader {
+            chunks,
+            chunk: io::Cursor::new(Vec::new()),
+        };
+        (PipeWriter(sender), reader)
+    }
+
+    /// Bot answering from thread, `bot` gets messages & writes answers
+    fn connect<F>(bot: F) -> (BotProcess, thread::JoinHandle<io::Result<()>>)
+    where
+        F: FnOnce(BufReader<PipeReader>, PipeWriter) -> io::Result<()> + Send + 'static,
+    {
+        let (stdin, input) = pipe();
+        let (output, stdout) = pipe();
+        let thread = thread::spawn(move || bot(BufReader::new(input), output));
+        (BotProcess::connect(None, stdin, stdout), thread)
+    }
+
+    fn stand_in(stand_in: StandIn) -> (BotProcess, thread::JoinHandle<io::Result<()>>) {
+        connect(move |input, output| run_stand_in(&stand_in, input, output))
+    }
+
+    /// Game of program contestants on small map
+    fn play_bots(
+        border_mode: BorderMode,
+        bots: Vec<(BotProcess, thread::JoinHandle<io::Result<()>>)>,
+    ) -> ContestReport {
+        let config = GameConfig {
+            map_width: 12,
+            map_height: 12,
+            border_mode,
+            ..GameConfig::default()
+        };
+        let contestants: Vec<_> = (0..bots.len())
+            .map(|index| Contestant::Program(BotCommand::parse(&format!("bot{index}")).unwrap()))
+            .collect();
+        let (processes, threads): (Vec<_>, Vec<_>) = bots
+            .into_iter()
+            .map(|(process, thread)| (Some(process), thread))
+            .unzip();
+        let report = play(
+            &config,
+            &contestants,
+            processes,
+            1,
+            Duration::from_secs(5),
+            200,
+        );
+        for thread in threads {
+            let _ = thread.join().unwrap();
+        }
+        report
+    }
+
+    #[test]
+    fn stand_ins_play_game_to_the_end() {
+        let straight = || stand_in(StandIn::Moves(vec![None]));
+        let report = play_bots(BorderMode::Solid, vec![straight(), straight()]);
+        assert!(report.ticks < 200);
+        assert!(report
+            .snakes
+            .iter()
+            .any(|snake| snake.death == Some(DeathCause::Wall)));
+        for snake in &report.snakes {
+            assert_eq!(snake.disqualified, None);
+            assert_eq!(snake.ticks_alive == report.ticks, snake.alive);
+        }
+    }
+
+    #[test]
+    fn stand_in_gets_start_ticks_and_end() {
+        let (process, thread) = connect(|input, mut output| {
+            let mut messages = Vec::new();
+            for line in input.lines() {
+                let message: BotMessage = serde_json::from_str(&line?).unwrap();
+                if let BotMessage::Tick { .. } = message {
+                    writeln!(output, r#"{{"direction": "left"}}"#)?;
+                }
+                messages.push(message);
+            }
+            assert!(matches!(messages[0], BotMessage::Start { you: 0, .. }));
+            assert!(matches!(messages[1], BotMessage::Tick { tick: 0, .. }));
+            assert!(matches!(
+                messages.last(),
+                Some(BotMessage::End { alive: false, .. })
+            ));
+            Ok(())
+        });
+        let report = play_bots(BorderMode::Solid, vec![(process, thread)]);
+        assert_eq!(report.snakes[0].death, Some(DeathCause::Wall));
+    }
+
+    #[test]
+    fn garbage_answer_disqualifies() {
+        let report = play_bots(
+            BorderMode::Wrap,
+            vec![
+                stand_in(StandIn::Garbage),
+                stand_in(StandIn::Level(BotLevel::Easy)),
+            ],
+        );
+        assert_eq!(
+            report.snakes[0].disqualified,
+            Some(Disqualification::InvalidAnswer("garbage".to_string()))
+        );
+        assert_eq!(report.snakes[0].death, Some(DeathCause::Disqualified));
+        assert_eq!(report.snakes[1].disqualified, None);
+    }
+
+    #[test]
+    fn early_quit_disqualifies() {
+        let report = play_bots(
+            BorderMode::Wrap,
+            vec![
+                stand_in(StandIn::Level(BotLevel::Easy)),
+                stand_in(StandIn::Quit),
+            ],
+        );
+        assert_eq!(report.snakes[0].disqualified, None);
+        assert_eq!(
+            report.snakes[1].disqualified,
+            Some(Disqualification::Exited)
+        );
+        assert_eq!(report.snakes[1].ticks_alive, 0);
+    }
+
+    #[test]
+    fn answer_without_question_disqualifies() {
+        // Every answer comes twice, extra answers pile up till noticed
+        let (process, thread) = connect(|input, mut output| {
+            for line in input.lines() {
+                if let BotMessage::Tick { .. } = serde_json::from_str(&line?).unwrap() {
+                    output.write_all(b"{\"direction\": null}\n{\"direction\": null}\n")?;
+                }
+            }
+            Ok(())
+        });
+        let report = play_bots(BorderMode::Wrap, vec![(process, thread)]);
+        assert!(matches!(
+            report.snakes[0].disqualified,
+            Some(Disqualification::UnexpectedAnswer(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bot_not_reading_input_times_out() {
+        let mut process = BotProcess::spawn(&BotCommand::parse("sleep 10").unwrap()).unwrap();
+        // Much more than pipe buffer, write blocks
+        let message = BotMessage::Tick {
+            tick: 0,
+            you: 0,
+            map_size: IVec2::new(1000, 1000),
+            snakes: Vec::new(),
+            meats: vec![IVec2::ZERO; 100_000],
+        };
+        let started = Instant::now();
+        process.send(&message).unwrap();
+        let answer = process.answer(started + Duration::from_millis(200));
+        assert!(matches!(answer, Err(Disqualification::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,14 +1,34 @@
 // Snake-rs
 // Mark Vodyanitskiy (@mvodya) 2024
 
-use bevy::prelude::*;
+use std::time::Duration;
 
-use crate::GameState;
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*, transform::TransformSystem};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
+use crate::{
+    net::{NetClient, NetPeer},
+    sim::{SimEvent, SimRules, SimState},
+    GameConfig, GameState,
+};
+
+mod attract;
 mod borders;
 mod interface;
 mod meat;
+mod network;
+mod pause;
+mod peer;
+mod recorder;
 mod snake;
+mod stats;
+
+pub use meat::MeatEaten;
+pub use recorder::{ReplayPlayer, ReplayRecorder};
+pub use snake::{player_color, Autopilot, SnakeCatastrophicEvent, SnakeCollisionEvent, SnakeDeath};
+#[allow(deprecated)]
+pub use stats::{GameResults, PlayerStats, SnakeStats};
 
 /// Stages for control movement game entities
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
@@ -21,83 +41,253 @@ enum MovementStages {
     Commit,
 }
 
+/// Stages for handling results of game tick (after MovementStages::Commit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+enum TickStages {
+    /// Translate simulation events to game events
+    Events,
+    /// React on game events
+    React,
+}
+
+/// Runs when new game starts (but not on resume from pause)
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OnGameStart;
+
+/// Runs when game stops (but not on pause)
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OnGameStop;
+
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(GameTickTimer(Timer::from_seconds(
-            0.2,
-            TimerMode::Repeating,
-        )))
-        .insert_resource(PlayerStats::default())
-        .add_plugins((
-            snake::SnakePlugin,
-            meat::MeatPlugin,
-            interface::GameInterfacePlugin,
-            borders::BordersPlugin,
-            bevy_spatial::AutomaticUpdate::<CollisionTracker>::new()
-                .with_spatial_ds(bevy_spatial::SpatialStructure::KDTree2)
-                .with_frequency(std::time::Duration::from_millis(1)),
-        ))
-        .add_systems(OnEnter(GameState::InGame), on_game_start)
-        .add_systems(OnExit(GameState::InGame), on_game_stop)
-        .add_systems(
-            Update,
-            (
-                game_tick_timer,
-                move_all_movable
-                    .in_set(MovementStages::Commit)
-                    .after(MovementStages::Calculate),
-                test_game_over,
-            ),
-        );
-    }
-}
-
-/// Game tick timer
+        app.init_resource::<GameConfig>()
+            .init_resource::<GameSeed>()
+            .insert_resource(GameRng::new(0))
+            .insert_resource(SpeedUp(false))
+            .insert_resource(Simulation(SimState::new(SimRules::default())))
+            .add_event::<SimulationEvent>()
+            .add_event::<SimulationRestored>()
+            .add_plugins((
+                attract::AttractPlugin,
+                snake::SnakePlugin,
+                meat::MeatPlugin,
+                interface::GameInterfacePlugin,
+                borders::BordersPlugin,
+                recorder::RecorderPlugin,
+                pause::PausePlugin,
+                stats::StatsPlugin,
+                network::NetworkPlugin,
+                peer::PeerPlugin,
+            ))
+            .configure_sets(
+                FixedUpdate,
+                (
+                    MovementStages::Input,
+                    MovementStages::Calculate,
+                    MovementStages::Commit,
+                    TickStages::Events,
+                    TickStages::React,
+                )
+                    .chain(),
+            )
+            .init_schedule(OnGameStart)
+            .init_schedule(OnGameStop)
+            .add_systems(OnGameStart, on_game_start)
+            .add_systems(OnGameStop, on_game_stop)
+            .add_systems(
+                OnTransition {
+                    from: GameState::MainMenu,
+                    to: GameState::InGame,
+                },
+                run_game_start,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::GameOver,
+                    to: GameState::InGame,
+                },
+                run_game_start,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::Victory,
+                    to: GameState::InGame,
+                },
+                run_game_start,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::InGame,
+                    to: GameState::GameOver,
+                },
+                run_game_stop,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::InGame,
+                    to: GameState::Victory,
+                },
+                run_game_stop,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::Paused,
+                    to: GameState::MainMenu,
+                },
+                run_game_stop,
+            )
+            .add_systems(Update, game_tick_rate.run_if(local_simulation))
+            .add_systems(
+                FixedUpdate,
+                (
+                    simulation_step
+                        .in_set(MovementStages::Calculate)
+                        .run_if(game_running)
+                        .run_if(local_simulation),
+                    test_game_over
+                        .in_set(TickStages::React)
+                        .run_if(in_state(GameState::InGame)),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                grid_to_transform.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Speed up of game tick was requested
 #[derive(Resource)]
-struct GameTickTimer(Timer);
+struct SpeedUp(bool);
+
+/// Seed for the games
+///
+/// Every game gets a new random seed if not set
+#[derive(Resource, Clone, Copy, Default)]
+pub struct GameSeed(pub Option<u64>);
 
-/// Player statistics
-#[derive(Resource, Clone, Copy)]
-pub struct PlayerStats {
-    pub score: u32,
-    pub food_eaten: u32,
-    pub distance_traveled: u32,
+/// Random generator for every random decision in the game
+#[derive(Resource, Clone)]
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng,
 }
 
-impl Default for PlayerStats {
-    fn default() -> Self {
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
         Self {
-            score: 0,
-            food_eaten: 0,
-            distance_traveled: 0,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
         }
     }
+
+    /// Seed of current game
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
 }
 
-/// Movable component
+/// Game rules state, entities of the game follow it
+#[derive(Resource)]
+pub struct Simulation(pub(crate) SimState);
+
+impl Simulation {
+    /// State after the last tick
+    pub fn state(&self) -> &SimState {
+        &self.0
+    }
+}
+
+/// Replace running game with `state`, for benchmarks
 ///
-/// Used for delayed movement (after MovementStages:Calculate stage)
-#[derive(Component)]
-struct Movable(Option<Vec2>);
+/// Entities of the game are placed again in the next tick.
+#[doc(hidden)]
+pub fn replace_simulation(world: &mut World, state: SimState) {
+    world.insert_resource(Simulation(state));
+    world.send_event(SimulationRestored);
+}
 
-/// Track collision with KDTree
-#[derive(Component, Default)]
-struct CollisionTracker;
+/// Wrapper for events produced by simulation tick
+#[derive(Event)]
+struct SimulationEvent(SimEvent);
 
-/// Alias for KDTree
-type NNTree = bevy_spatial::kdtree::KDTree2<CollisionTracker>;
+/// Simulation state was replaced by restored one instead of tick
+#[derive(Event)]
+struct SimulationRestored;
 
-/// Handle game tick
-fn game_tick_timer(
-    time: Res<Time>,
-    mut timer: ResMut<GameTickTimer>,
+/// Bot game is played behind main menu
+#[derive(Resource)]
+struct Attract;
+
+/// Game systems run in real game and in attract mode
+fn game_running(state: Res<State<GameState>>, attract: Option<Res<Attract>>) -> bool {
+    *state.get() == GameState::InGame || attract.is_some()
+}
+
+/// Game is simulated here, not by server or together with peer
+fn local_simulation(client: Option<Res<NetClient>>, peer: Option<Res<NetPeer>>) -> bool {
+    client.is_none() && peer.is_none()
+}
+
+/// Board cell of game entity
+///
+/// Game logic works only with cells, `Transform` is derived from it for
+/// rendering
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridPos(pub IVec2);
+
+/// Convert board cell to world position
+fn cell_to_world(pos: IVec2, map_size: IVec2) -> Vec3 {
+    (pos.as_vec2() - map_size.as_vec2() / 2. + 0.5).extend(0.)
+}
+
+/// Place moved entities in the world
+fn grid_to_transform(
+    simulation: Res<Simulation>,
+    mut query: Query<(&GridPos, &mut Transform), Changed<GridPos>>,
+) {
+    for (pos, mut transform) in &mut query {
+        transform.translation = cell_to_world(pos.0, simulation.0.rules.map_size);
+    }
+}
+
+/// Handle game tick rate
+///
+/// Every game tick runs exactly once in FixedUpdate schedule
+fn game_tick_rate(
+    mut time: ResMut<Time<Fixed>>,
+    config: Res<GameConfig>,
     keys: Res<ButtonInput<KeyCode>>,
     gamepads: Res<Gamepads>,
     gamepad_inputs: Res<ButtonInput<GamepadButton>>,
+    player: Option<Res<ReplayPlayer>>,
+    mut speed_up: ResMut<SpeedUp>,
 ) {
     let mut request_speed_up = false;
+    // Get speed up from replay
+    if let Some(player) = player {
+        request_speed_up = player.peek().is_some_and(|tick| tick.speed_up);
+    }
     // Get keyboard input
     if keys.any_pressed([KeyCode::ShiftLeft]) {
         request_speed_up = true;
@@ -116,60 +306,95 @@ fn game_tick_timer(
     }
 
     // Speed up movement
-    let speed_up;
-    if request_speed_up {
-        speed_up = 2;
+    speed_up.0 = request_speed_up;
+    let multiplier = if request_speed_up {
+        config.speed_up
     } else {
-        speed_up = 1;
+        1.
+    };
+    // Update game tick period
+    let period = Duration::from_secs_f64(config.tick_seconds / multiplier);
+    if time.timestep() != period {
+        time.set_timestep(period);
     }
-    // Update game tick
-    timer.0.tick(time.delta() * speed_up);
 }
 
-/// When InGame state enter
-fn on_game_start(mut stats: ResMut<PlayerStats>) {
-    debug!("Init game");
-    // Reset player statistics
-    *stats = PlayerStats::default();
+/// Start new game
+fn run_game_start(world: &mut World) {
+    world.run_schedule(OnGameStart);
 }
 
-/// When InGame state exit
+/// Stop current game
+fn run_game_stop(world: &mut World) {
+    world.run_schedule(OnGameStop);
+}
+
+/// When new game started
+fn on_game_start(
+    mut simulation: ResMut<Simulation>,
+    seed: Res<GameSeed>,
+    mut rng: ResMut<GameRng>,
+    player: Option<ResMut<ReplayPlayer>>,
+    config: Res<GameConfig>,
+    attract: Option<Res<Attract>>,
+) {
+    // Seed random generator, replay brings own seed
+    let seed = match player {
+        Some(mut player) => {
+            player.rewind();
+            player.seed()
+        }
+        None => seed.0.unwrap_or_else(rand::random),
+    };
+    *rng = GameRng::new(seed);
+    debug!("Init game with seed {}", rng.seed());
+    // Reset board, bots behind main menu play till the end
+    let mut rules = config.rules();
+    if attract.is_some() {
+        rules.players = 0;
+    }
+    simulation.0 = SimState::new(rules);
+}
+
+/// When game stopped
 fn on_game_stop() {
     debug!("Stop game");
 }
 
-/// Apply all movement
-fn move_all_movable(
-    mut movable: Query<(&mut Movable, &mut Transform)>,
-    timer: ResMut<GameTickTimer>,
+/// Run simulation tick with collected snake inputs
+///
+/// Game state changes only between frames, so several ticks of one frame
+/// may come after the end of game. Finished game is never simulated further.
+fn simulation_step(
+    mut simulation: ResMut<Simulation>,
+    mut snakes: Query<&mut snake::Snake>,
+    mut ev_simulation: EventWriter<SimulationEvent>,
+    mut rng: ResMut<GameRng>,
 ) {
-    if timer.0.just_finished() {
-        for (mut movable, mut transform) in &mut movable {
-            if let Some(mut pos) = movable.0 {
-                // Portal on borders of world
-                let hx = crate::MAP_SIZE.x / 2.;
-                let hy = crate::MAP_SIZE.y / 2.;
-                pos.x = ((pos.x + hx) % crate::MAP_SIZE.x) - hx;
-                if pos.x < -hx {
-                    pos.x = hx - 1.;
-                }
-                pos.y = ((pos.y + hy) % crate::MAP_SIZE.y) - hy;
-                if pos.y < -hy {
-                    pos.y = hy - 1.;
-                }
-                // Make transform
-                transform.translation = pos.extend(0.);
-                movable.0 = None;
-            }
+    if simulation.0.is_over() {
+        return;
+    }
+
+    // Collect inputs
+    let mut inputs = vec![None; simulation.0.snakes.len()];
+    for mut snake in &mut snakes {
+        if let Some(input) = inputs.get_mut(snake.index) {
+            *input = snake.input.take();
         }
     }
+
+    let events = simulation.0.step(&inputs, &mut *rng);
+    ev_simulation.send_batch(events.into_iter().map(SimulationEvent));
 }
 
-fn test_game_over(
-    mut ev_snake_catastrophic: EventReader<snake::SnakeCatastrophicEvent>,
-    mut next_state: ResMut<NextState<GameState>>,
-) {
-    for _ev in ev_snake_catastrophic.read() {
+fn test_game_over(simulation: Res<Simulation>, mut next_state: ResMut<NextState<GameState>>) {
+    if !simulation.0.is_over() {
+        return;
+    }
+    if simulation.0.board_full {
+        info!("Board is full, victory");
+        next_state.set(GameState::Victory);
+    } else {
         next_state.set(GameState::GameOver);
     }
 }
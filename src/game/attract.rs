@@ -0,0 +1,68 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+use bevy::prelude::*;
+
+use crate::{bot::BotLevel, GameState};
+
+use super::{
+    snake::{Snake, SnakeBot},
+    Attract, OnGameStart, OnGameStop, ReplayPlayer, Simulation, TickStages,
+};
+
+/// Bots play the game behind main menu, like arcade attract mode
+pub struct AttractPlugin;
+
+impl Plugin for AttractPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::MainMenu),
+            start_attract
+                .run_if(super::local_simulation)
+                .run_if(not(resource_exists::<ReplayPlayer>)),
+        )
+        .add_systems(
+            OnExit(GameState::MainMenu),
+            stop_attract.run_if(resource_exists::<Attract>),
+        )
+        .add_systems(
+            FixedUpdate,
+            restart_attract
+                .in_set(TickStages::React)
+                .run_if(resource_exists::<Attract>),
+        );
+    }
+}
+
+/// Level of bots playing behind main menu
+const ATTRACT_BOT: BotLevel = BotLevel::Hard;
+
+/// Start bot game, results of real games are kept
+fn start_attract(world: &mut World) {
+    world.insert_resource(Attract);
+    world.run_schedule(OnGameStart);
+
+    // Every snake is driven by bot
+    let heads: Vec<Entity> = world
+        .query_filtered::<Entity, (With<Snake>, Without<SnakeBot>)>()
+        .iter(world)
+        .collect();
+    for head in heads {
+        world.entity_mut(head).insert(SnakeBot(ATTRACT_BOT));
+    }
+}
+
+/// Stop bot game before real game
+fn stop_attract(world: &mut World) {
+    world.run_schedule(OnGameStop);
+    world.remove_resource::<Attract>();
+}
+
+/// Play bot game again after it is over
+fn restart_attract(world: &mut World) {
+    if !world.resource::<Simulation>().0.is_over() {
+        return;
+    }
+    world.run_schedule(OnGameStop);
+    start_attract(world);
+}
@@ -3,36 +3,44 @@
 
 use bevy::prelude::*;
 
-use crate::{GameState, MAP_SIZE};
+use crate::{sim::BorderMode, GameConfig};
+
+use super::{OnGameStart, OnGameStop};
 
 pub struct BordersPlugin;
 
 impl Plugin for BordersPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::InGame), spawn_borders)
-            .add_systems(OnExit(GameState::InGame), despawn_borders);
+        app.add_systems(OnGameStart, spawn_borders)
+            .add_systems(OnGameStop, despawn_borders);
     }
 }
 
+/// Map edge, contains direction pointing outside of the map
 #[derive(Component)]
-struct Border;
+pub struct Border(pub IVec2);
 
-fn spawn_borders(mut commands: Commands) {
-    let color = Color::rgb(0.5, 0.5, 0.5);
+fn spawn_borders(mut commands: Commands, config: Res<GameConfig>) {
+    // Solid walls are brighter
+    let color = match config.border_mode {
+        BorderMode::Wrap => Color::rgb(0.5, 0.5, 0.5),
+        BorderMode::Solid => Color::rgb(0.9, 0.9, 0.9),
+    };
+    let map_size = config.map_size().as_vec2();
 
     // Up
     commands.spawn((
-        Border,
+        Border(IVec2::Y),
         SpriteBundle {
             sprite: Sprite {
-                color: color,
-                custom_size: Vec2::new(MAP_SIZE.x, 0.5).into(),
+                color,
+                custom_size: Vec2::new(map_size.x, 0.5).into(),
                 ..default()
             },
             transform: Transform {
                 translation: Vec3 {
                     x: 0.,
-                    y: MAP_SIZE.y / 2.,
+                    y: map_size.y / 2.,
                     z: -1.,
                 },
                 ..default()
@@ -43,17 +51,17 @@ fn spawn_borders(mut commands: Commands) {
 
     // Down
     commands.spawn((
-        Border,
+        Border(IVec2::NEG_Y),
         SpriteBundle {
             sprite: Sprite {
-                color: color,
-                custom_size: Vec2::new(MAP_SIZE.x, 0.5).into(),
+                color,
+                custom_size: Vec2::new(map_size.x, 0.5).into(),
                 ..default()
             },
             transform: Transform {
                 translation: Vec3 {
                     x: 0.,
-                    y: -MAP_SIZE.y / 2.,
+                    y: -map_size.y / 2.,
                     z: -1.,
                 },
                 ..default()
@@ -64,16 +72,16 @@ fn spawn_borders(mut commands: Commands) {
 
     // Left
     commands.spawn((
-        Border,
+        Border(IVec2::NEG_X),
         SpriteBundle {
             sprite: Sprite {
-                color: color,
-                custom_size: Vec2::new(0.5, MAP_SIZE.y).into(),
+                color,
+                custom_size: Vec2::new(0.5, map_size.y).into(),
                 ..default()
             },
             transform: Transform {
                 translation: Vec3 {
-                    x: -MAP_SIZE.x / 2.,
+                    x: -map_size.x / 2.,
                     y: 0.,
                     z: -1.,
                 },
@@ -85,16 +93,16 @@ fn spawn_borders(mut commands: Commands) {
 
     // Right
     commands.spawn((
-        Border,
+        Border(IVec2::X),
         SpriteBundle {
             sprite: Sprite {
-                color: color,
-                custom_size: Vec2::new(0.5, MAP_SIZE.y).into(),
+                color,
+                custom_size: Vec2::new(0.5, map_size.y).into(),
                 ..default()
             },
             transform: Transform {
                 translation: Vec3 {
-                    x: MAP_SIZE.x / 2.,
+                    x: map_size.x / 2.,
                     y: 0.,
                     z: -1.,
                 },
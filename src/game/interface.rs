@@ -6,26 +6,29 @@ use bevy::{
     prelude::*,
 };
 
-use crate::GameState;
+use crate::{GameConfig, GameState};
 
-use super::PlayerStats;
+use super::{
+    player_color, snake::Snake, stats::SnakeStats, Attract, Autopilot, OnGameStart, OnGameStop,
+};
 
 pub struct GameInterfacePlugin;
 
 impl Plugin for GameInterfacePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(FrameTimeDiagnosticsPlugin)
-            .add_systems(OnEnter(GameState::InGame), setup)
-            .add_systems(OnExit(GameState::InGame), despawn_all_ui)
+            .add_systems(OnGameStart, setup.run_if(not(resource_exists::<Attract>)))
+            .add_systems(OnGameStop, despawn_all_ui)
             .add_systems(
                 Update,
-                (fps_text_update, score_text_update).run_if(in_state(GameState::InGame)),
+                (fps_text_update, score_text_update, autopilot_text_update)
+                    .run_if(in_state(GameState::InGame)),
             );
     }
 }
 
 #[derive(Component)]
-struct HUD;
+struct Hud;
 
 #[derive(Component)]
 struct FpsText;
@@ -33,35 +36,83 @@ struct FpsText;
 #[derive(Component)]
 struct ScoreText;
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+#[derive(Component)]
+struct AutopilotText;
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, config: Res<GameConfig>) {
+    // Score section for every snake
+    let mut sections = vec![TextSection::new(
+        "SCORE: ",
+        TextStyle {
+            font: asset_server.load("fonts/Minimal3x5.ttf"),
+            font_size: 30.0,
+            ..default()
+        },
+    )];
+    for index in 0..config.rules().snakes {
+        sections.push(TextSection::new(
+            "???",
+            TextStyle {
+                font: asset_server.load("fonts/Minimal3x5.ttf"),
+                font_size: 30.0,
+                color: player_color(index),
+            },
+        ));
+    }
+
     commands.spawn((
-        TextBundle::from_sections([
-            TextSection::new(
-                "SCORE: ",
-                TextStyle {
-                    font: asset_server.load("fonts/Minimal3x5.ttf"),
-                    font_size: 30.0,
-                    ..default()
-                },
-            ),
-            TextSection::new(
-                "???",
-                TextStyle {
-                    font: asset_server.load("fonts/Minimal3x5.ttf"),
-                    font_size: 30.0,
-                    ..default()
-                },
-            ),
-        ])
-        .with_text_justify(JustifyText::Center)
+        TextBundle::from_sections(sections)
+            .with_text_justify(JustifyText::Center)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
+                ..default()
+            }),
+        Hud,
+        ScoreText,
+    ));
+
+    // Borders mode
+    commands.spawn((
+        TextBundle::from_section(
+            format!("WALLS: {}", config.border_mode).to_uppercase(),
+            TextStyle {
+                font: asset_server.load("fonts/Minimal3x5.ttf"),
+                font_size: 20.0,
+                color: Color::GRAY,
+            },
+        )
         .with_style(Style {
             position_type: PositionType::Absolute,
-            top: Val::Px(10.0),
+            top: Val::Px(45.0),
             left: Val::Px(10.0),
             ..default()
         }),
-        HUD,
-        ScoreText,
+        Hud,
+    ));
+
+    // Shown while autopilot drives player snake
+    commands.spawn((
+        TextBundle {
+            visibility: Visibility::Hidden,
+            ..TextBundle::from_section(
+                "AUTOPILOT",
+                TextStyle {
+                    font: asset_server.load("fonts/Minimal3x5.ttf"),
+                    font_size: 30.0,
+                    color: Color::rgb(0.3, 1., 0.3),
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(70.0),
+                left: Val::Px(10.0),
+                ..default()
+            })
+        },
+        Hud,
+        AutopilotText,
     ));
 
     commands.spawn((
@@ -87,12 +138,12 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             right: Val::Px(10.0),
             ..default()
         }),
-        HUD,
+        Hud,
         FpsText,
     ));
 }
 
-fn despawn_all_ui(mut commands: Commands, query: Query<Entity, With<HUD>>) {
+fn despawn_all_ui(mut commands: Commands, query: Query<Entity, With<Hud>>) {
     for entity in query.iter() {
         commands.entity(entity).despawn();
     }
@@ -109,8 +160,34 @@ fn fps_text_update(diagnostics: Res<DiagnosticsStore>, mut query: Query<&mut Tex
     }
 }
 
-fn score_text_update(stats: Res<PlayerStats>, mut query: Query<&mut Text, With<ScoreText>>) {
+fn score_text_update(
+    snakes: Query<(&Snake, &SnakeStats)>,
+    mut query: Query<&mut Text, With<ScoreText>>,
+) {
+    let single = snakes.iter().len() == 1;
     for mut text in &mut query {
-        text.sections[1].value = format!("{}", stats.score);
+        for (snake, stats) in &snakes {
+            let Some(section) = text.sections.get_mut(snake.index + 1) else {
+                continue;
+            };
+            section.value = if single {
+                format!("{}", stats.score)
+            } else {
+                format!(" P{} {}", snake.index + 1, stats.score)
+            };
+        }
+    }
+}
+
+fn autopilot_text_update(
+    autopilot: Res<Autopilot>,
+    mut query: Query<&mut Visibility, With<AutopilotText>>,
+) {
+    for mut visibility in &mut query {
+        visibility.set_if_neq(if autopilot.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
     }
 }
@@ -1,121 +1,125 @@
 // Snake-rs
 // Mark Vodyanitskiy (@mvodya) 2024
 
+use std::collections::HashSet;
+
 use bevy::prelude::*;
-use rand::Rng;
 
-use crate::GameState;
+use crate::sim::SimEvent;
 
-use super::{snake::SnakeCollisionEvent, CollisionTracker, MovementStages};
+use super::{
+    cell_to_world, snake::Snake, GridPos, OnGameStop, Simulation, SimulationEvent, TickStages,
+};
 
 pub struct MeatPlugin;
 
 impl Plugin for MeatPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<MeatEaten>()
-            .insert_resource(MeatSpawnerTickTimer(Timer::from_seconds(
-                3.0,
-                TimerMode::Repeating,
-            )))
             .add_systems(
-                Update,
+                FixedUpdate,
                 (
-                    meat_spawner_tick_timer,
-                    meat_spawner,
-                    snake_collision_with_meat,
+                    snake_collision_with_meat.in_set(TickStages::Events),
+                    sync_meats.in_set(TickStages::React),
                 )
-                    .after(MovementStages::Commit)
-                    .run_if(in_state(GameState::InGame)),
+                    .run_if(super::game_running),
             )
-            .add_systems(OnExit(GameState::InGame), despawn_all_meats);
+            .add_systems(OnGameStop, despawn_all_meats);
     }
 }
 
+/// Meat component, contains id of simulated meat
 #[derive(Component)]
-struct Meat(Color);
-
-// Meat spawn dealy
-#[derive(Resource)]
-struct MeatSpawnerTickTimer(Timer);
+pub struct Meat(pub u32);
 
 /// Called when snake eats the meat
 #[derive(Event)]
 pub struct MeatEaten {
     pub snake: Entity,
     pub position: Vec2,
+    /// Board cell of eaten meat
+    pub cell: IVec2,
     pub color: Color,
 }
 
-/// Handle game tick
-fn meat_spawner_tick_timer(time: Res<Time>, mut timer: ResMut<MeatSpawnerTickTimer>) {
-    timer.0.tick(time.delta());
+/// Color of meat by hue
+fn meat_color(hue: f32) -> Color {
+    Color::hsl(hue, 0.9, 0.4)
 }
 
-/// Meat spawner
-fn meat_spawner(timer: ResMut<MeatSpawnerTickTimer>, meats: Query<&Meat>, mut commands: Commands) {
-    if !timer.0.just_finished() {
-        return;
-    }
-
-    // Get count of spawned meat
-    let count = meats.iter().count();
-
-    // There are no need spawn mode
-    if count >= 9 {
-        return;
+/// Keep meat entities matching simulated meat
+///
+/// Meat is spawned and removed by simulation state, so restored state
+/// is shown right too
+fn sync_meats(
+    mut commands: Commands,
+    meats: Query<(Entity, &Meat, &GridPos)>,
+    simulation: Res<Simulation>,
+) {
+    let simulated: HashSet<(u32, IVec2)> = simulation
+        .0
+        .meats
+        .iter()
+        .map(|sim_meat| (sim_meat.id, sim_meat.position))
+        .collect();
+    let mut shown = HashSet::with_capacity(simulated.len());
+
+    // Remove eaten meat
+    for (entity, meat, pos) in &meats {
+        if simulated.contains(&(meat.0, pos.0)) {
+            shown.insert((meat.0, pos.0));
+        } else {
+            commands.entity(entity).despawn();
+        }
     }
 
-    // Calculate position
-    let mut rng = rand::thread_rng();
-    let hx = (crate::MAP_SIZE.x / 2.) as i32 - 1;
-    let hy = (crate::MAP_SIZE.y / 2.) as i32 - 1;
-    let x = rng.gen_range(-hx..=hx);
-    let y = rng.gen_range(-hy..=hy);
-    let pos = Vec3::new(x as f32, y as f32, 0.);
-    let color = Color::hsl(rng.gen_range(0. ..=360.), 0.9, 0.4);
-
-    // Spawn
-    commands.spawn((
-        Meat(color),
-        SpriteBundle {
-            sprite: Sprite {
-                color: color,
-                custom_size: Vec2::new(1., 1.).into(),
-                ..default()
-            },
-            transform: Transform {
-                translation: pos,
+    // Spawn new meat
+    for sim_meat in &simulation.0.meats {
+        if shown.contains(&(sim_meat.id, sim_meat.position)) {
+            continue;
+        }
+        commands.spawn((
+            Meat(sim_meat.id),
+            GridPos(sim_meat.position),
+            SpriteBundle {
+                sprite: Sprite {
+                    color: meat_color(sim_meat.hue),
+                    custom_size: Vec2::new(1., 1.).into(),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        },
-        CollisionTracker,
-    ));
+        ));
+    }
 }
 
 fn snake_collision_with_meat(
-    mut ev_snake_collision: EventReader<SnakeCollisionEvent>,
+    mut ev_simulation: EventReader<SimulationEvent>,
     mut ev_meat_eaten: EventWriter<MeatEaten>,
-    meats: Query<&Meat>,
-    mut commands: Commands,
+    simulation: Res<Simulation>,
+    snakes: Query<(Entity, &Snake)>,
 ) {
-    for ev in ev_snake_collision.read() {
-        // Check is other_entity is SnakeBody
-        if meats.contains(ev.other) {
-            let meat = meats.get(ev.other).unwrap();
-            // Send event
-            ev_meat_eaten.send(MeatEaten {
-                snake: ev.snake,
-                position: ev.position,
-                color: meat.0,
-            });
-            debug!(
-                "Snake {:?} eats meat {:?} at {:?}",
-                ev.snake, ev.position, ev.other
-            );
-            // Remove meat
-            commands.entity(ev.other).despawn();
-        }
+    for ev in ev_simulation.read() {
+        let SimEvent::MeatEaten {
+            snake,
+            meat,
+            position,
+            hue,
+        } = ev.0
+        else {
+            continue;
+        };
+        let Some((snake, _)) = snakes.iter().find(|(_, s)| s.index == snake) else {
+            continue;
+        };
+        // Send event
+        ev_meat_eaten.send(MeatEaten {
+            snake,
+            position: cell_to_world(position, simulation.0.rules.map_size).truncate(),
+            cell: position,
+            color: meat_color(hue),
+        });
+        debug!("Snake {:?} eats meat {:?} at {:?}", snake, meat, position);
     }
 }
 
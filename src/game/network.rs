@@ -0,0 +1,95 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+use std::{sync::mpsc::TryRecvError, time::Duration};
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    net::{ClientMessage, NetClient, ServerMessage},
+    GameConfig, GameState,
+};
+
+use super::{snake::Snake, GameRng, MovementStages, OnGameStart, Simulation, SimulationEvent};
+
+/// Game ticks per server tick, server is polled more often than it ticks
+const NET_POLL_RATE: f64 = 4.;
+
+/// Plays game simulated by server, works only with `NetClient` resource
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnGameStart,
+            client_ready
+                .after(super::on_game_start)
+                .run_if(resource_exists::<NetClient>),
+        )
+        .add_systems(Update, net_tick_rate.run_if(resource_exists::<NetClient>))
+        .add_systems(
+            FixedUpdate,
+            (send_input, receive_ticks)
+                .chain()
+                .in_set(MovementStages::Calculate)
+                .run_if(resource_exists::<NetClient>)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// Server controls speed of the game
+fn net_tick_rate(mut time: ResMut<Time<Fixed>>, config: Res<GameConfig>) {
+    let period = Duration::from_secs_f64(config.tick_seconds / NET_POLL_RATE);
+    if time.timestep() != period {
+        time.set_timestep(period);
+    }
+}
+
+/// Ask server for new game
+fn client_ready(client: Res<NetClient>) {
+    if let Err(err) = client.send(&ClientMessage::Ready) {
+        error!("Failed to send to server: {}", err);
+    }
+}
+
+/// Send input of own snake to server
+fn send_input(client: Res<NetClient>, mut snakes: Query<&mut Snake>) {
+    for mut snake in &mut snakes {
+        if snake.index != client.player {
+            continue;
+        }
+        let Some(direction) = snake.input.take() else {
+            continue;
+        };
+        if let Err(err) = client.send(&ClientMessage::Input(direction)) {
+            error!("Failed to send to server: {}", err);
+        }
+    }
+}
+
+/// Apply states received from server
+fn receive_ticks(
+    client: Res<NetClient>,
+    mut simulation: ResMut<Simulation>,
+    mut rng: ResMut<GameRng>,
+    mut ev_simulation: EventWriter<SimulationEvent>,
+    mut ev_app_exit: EventWriter<AppExit>,
+) {
+    loop {
+        match client.try_recv() {
+            Ok(Some(ServerMessage::Start { seed })) => *rng = GameRng::new(seed),
+            Ok(Some(ServerMessage::Tick { state, events })) => {
+                simulation.0 = state;
+                ev_simulation.send_batch(events.into_iter().map(SimulationEvent));
+            }
+            Ok(Some(ServerMessage::Welcome { .. })) => {}
+            Err(TryRecvError::Empty) => break,
+            Ok(None) | Err(TryRecvError::Disconnected) => {
+                error!("Lost connection to server");
+                ev_app_exit.send(AppExit);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+use bevy::{prelude::*, window::WindowFocused};
+
+use crate::{
+    net::{NetClient, NetPeer},
+    GameState,
+};
+
+use super::{OnGameStart, OnGameStop};
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (pause_input, pause_on_focus_lost).run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(OnEnter(GameState::Paused), show_pause_menu)
+        .add_systems(
+            Update,
+            (pause_menu_input, pause_menu_text_update)
+                .chain()
+                .run_if(in_state(GameState::Paused)),
+        )
+        .add_systems(OnExit(GameState::Paused), hide_pause_menu);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PauseAction {
+    Resume,
+    Restart,
+    Quit,
+}
+
+const PAUSE_ACTIONS: [(PauseAction, &str); 3] = [
+    (PauseAction::Resume, "resume"),
+    (PauseAction::Restart, "restart"),
+    (PauseAction::Quit, "quit to menu"),
+];
+
+/// Pause overlay root
+#[derive(Component)]
+struct PauseMenu;
+
+/// Pause menu text
+#[derive(Component)]
+struct PauseMenuText {
+    /// Index of selected action
+    selected: usize,
+    actions: &'static [(PauseAction, &'static str)],
+}
+
+/// Available pause actions
+///
+/// Network game can be only resumed, restart or quit of one player would
+/// leave other players in another game
+fn pause_actions(
+    client: Option<Res<NetClient>>,
+    peer: Option<Res<NetPeer>>,
+) -> &'static [(PauseAction, &'static str)] {
+    if client.is_some() || peer.is_some() {
+        &PAUSE_ACTIONS[..1]
+    } else {
+        &PAUSE_ACTIONS
+    }
+}
+
+/// Check pause toggle buttons
+fn pause_pressed(
+    keys: &ButtonInput<KeyCode>,
+    gamepads: &Gamepads,
+    gamepad_inputs: &ButtonInput<GamepadButton>,
+) -> bool {
+    if keys.any_just_pressed([KeyCode::Escape, KeyCode::KeyP]) {
+        return true;
+    }
+    gamepads.iter().any(|gamepad| {
+        gamepad_inputs.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Start))
+    })
+}
+
+fn pause_input(
+    mut next_state: ResMut<NextState<GameState>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_inputs: Res<ButtonInput<GamepadButton>>,
+) {
+    if pause_pressed(&keys, &gamepads, &gamepad_inputs) {
+        next_state.set(GameState::Paused);
+    }
+}
+
+/// Pause game when window loses focus
+fn pause_on_focus_lost(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut ev_window_focused: EventReader<WindowFocused>,
+) {
+    for ev in ev_window_focused.read() {
+        if !ev.focused {
+            next_state.set(GameState::Paused);
+        }
+    }
+}
+
+fn show_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    client: Option<Res<NetClient>>,
+    peer: Option<Res<NetPeer>>,
+) {
+    let actions = pause_actions(client, peer);
+    let mut sections = vec![TextSection::new(
+        "PAUSE\n",
+        TextStyle {
+            font: asset_server.load("fonts/Minimal5x7.ttf"),
+            font_size: 80.,
+            ..default()
+        },
+    )];
+    for _ in actions {
+        sections.push(TextSection::from_style(TextStyle {
+            font: asset_server.load("fonts/Minimal3x5.ttf"),
+            font_size: 30.,
+            ..default()
+        }));
+    }
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            PauseMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_sections(sections).with_text_justify(JustifyText::Center),
+                PauseMenuText {
+                    selected: 0,
+                    actions,
+                },
+            ));
+        });
+}
+
+fn pause_menu_input(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut query: Query<&mut PauseMenuText>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_inputs: Res<ButtonInput<GamepadButton>>,
+) {
+    // Resume with same buttons
+    if pause_pressed(&keys, &gamepads, &gamepad_inputs) {
+        next_state.set(GameState::InGame);
+        return;
+    }
+
+    let gamepad_pressed = |button| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_inputs.just_pressed(GamepadButton::new(gamepad, button)))
+    };
+
+    for mut menu in &mut query {
+        // Select action
+        if keys.any_just_pressed([KeyCode::ArrowUp, KeyCode::KeyW])
+            || gamepad_pressed(GamepadButtonType::DPadUp)
+        {
+            menu.selected = (menu.selected + menu.actions.len() - 1) % menu.actions.len();
+        }
+        if keys.any_just_pressed([KeyCode::ArrowDown, KeyCode::KeyS])
+            || gamepad_pressed(GamepadButtonType::DPadDown)
+        {
+            menu.selected = (menu.selected + 1) % menu.actions.len();
+        }
+
+        // Apply action
+        if !keys.any_just_pressed([KeyCode::Enter, KeyCode::Space])
+            && !gamepad_pressed(GamepadButtonType::South)
+        {
+            continue;
+        }
+        match menu.actions[menu.selected].0 {
+            PauseAction::Resume => next_state.set(GameState::InGame),
+            PauseAction::Restart => {
+                commands.add(|world: &mut World| {
+                    world.run_schedule(OnGameStop);
+                    world.run_schedule(OnGameStart);
+                });
+                next_state.set(GameState::InGame);
+            }
+            PauseAction::Quit => next_state.set(GameState::MainMenu),
+        }
+    }
+}
+
+fn pause_menu_text_update(mut query: Query<(&PauseMenuText, &mut Text), Changed<PauseMenuText>>) {
+    for (menu, mut text) in &mut query {
+        for (index, (_, label)) in menu.actions.iter().enumerate() {
+            let section = &mut text.sections[index + 1];
+            if index == menu.selected {
+                section.value = format!("\n> {label} <");
+                section.style.color = Color::WHITE;
+            } else {
+                section.value = format!("\n{label}");
+                section.style.color = Color::GRAY;
+            }
+        }
+    }
+}
+
+fn hide_pause_menu(mut commands: Commands, query: Query<Entity, With<PauseMenu>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,162 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    net::{NetPeer, PeerMessage},
+    rollback::{PeerInputs, RollbackSession},
+    GameConfig, GameState,
+};
+
+use super::{
+    snake::Snake, GameRng, MovementStages, OnGameStart, Simulation, SimulationEvent,
+    SimulationRestored,
+};
+
+/// Plays peer-to-peer game with rollback, works only with `NetPeer` resource
+pub struct PeerPlugin;
+
+impl Plugin for PeerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnGameStart,
+            start_session
+                .after(super::on_game_start)
+                .run_if(resource_exists::<NetPeer>),
+        )
+        .add_systems(Update, peer_tick_rate.run_if(resource_exists::<NetPeer>))
+        .add_systems(
+            FixedUpdate,
+            // Inputs are exchanged on pause and game over too, other peer may wait for them
+            (
+                receive_inputs,
+                peer_step.run_if(in_state(GameState::InGame)),
+                send_inputs,
+            )
+                .chain()
+                .in_set(MovementStages::Calculate)
+                .run_if(resource_exists::<PeerGame>),
+        );
+    }
+}
+
+/// Current peer-to-peer game
+#[derive(Resource)]
+struct PeerGame {
+    /// Number of game since connection, both peers count games
+    number: u32,
+    session: RollbackSession,
+    /// Inputs of next games from faster peer
+    early: Vec<(u32, PeerInputs)>,
+}
+
+/// Both peers play without speed up
+fn peer_tick_rate(mut time: ResMut<Time<Fixed>>, config: Res<GameConfig>) {
+    let period = Duration::from_secs_f64(config.tick_seconds);
+    if time.timestep() != period {
+        time.set_timestep(period);
+    }
+}
+
+/// Start rollback session of new game
+fn start_session(
+    mut commands: Commands,
+    peer: Res<NetPeer>,
+    game: Option<ResMut<PeerGame>>,
+    simulation: Res<Simulation>,
+    mut rng: ResMut<GameRng>,
+) {
+    let (number, early) = match game {
+        Some(mut game) => (game.number + 1, std::mem::take(&mut game.early)),
+        None => (0, Vec::new()),
+    };
+    // Peers must use same seed
+    *rng = GameRng::new(peer.game_seed(number));
+    let mut session = RollbackSession::new(simulation.0.clone(), rng.clone(), peer.player);
+    for (_, inputs) in early.iter().filter(|(game, _)| *game == number) {
+        session.add_inputs(inputs);
+    }
+    let early = early
+        .into_iter()
+        .filter(|(game, _)| *game > number)
+        .collect();
+    commands.insert_resource(PeerGame {
+        number,
+        session,
+        early,
+    });
+}
+
+/// Save inputs received from other peer
+fn receive_inputs(peer: Res<NetPeer>, mut game: ResMut<PeerGame>) {
+    let game = &mut *game;
+    loop {
+        match peer.try_recv() {
+            Ok(Some(PeerMessage::Inputs {
+                game: number,
+                inputs,
+            })) => {
+                if number == game.number {
+                    game.session.add_inputs(&inputs);
+                } else if number > game.number {
+                    game.early.push((number, inputs));
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(err) => {
+                error!("Failed to receive from peer: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+/// Run game tick, restore game first when prediction was wrong
+fn peer_step(
+    peer: Res<NetPeer>,
+    mut game: ResMut<PeerGame>,
+    mut simulation: ResMut<Simulation>,
+    mut snakes: Query<&mut Snake>,
+    mut ev_simulation: EventWriter<SimulationEvent>,
+    mut ev_restored: EventWriter<SimulationRestored>,
+) {
+    let mut restored = game.session.rollback();
+    if restored {
+        debug!("Rollback, {} rollbacks in game", game.session.rollbacks());
+    }
+    if game.session.can_advance() {
+        let input = snakes
+            .iter_mut()
+            .find(|snake| snake.index == peer.player)
+            .and_then(|mut snake| snake.input.take());
+        let events = game.session.advance(input);
+        ev_simulation.send_batch(events.into_iter().map(SimulationEvent));
+    }
+
+    // Game ends only with inputs of both peers
+    let state = if game.session.state().is_over() {
+        restored = true;
+        game.session.confirmed_state()
+    } else {
+        game.session.state()
+    };
+    simulation.0 = state.clone();
+    if restored {
+        ev_restored.send(SimulationRestored);
+    }
+}
+
+/// Send local inputs not received by other peer yet
+fn send_inputs(peer: Res<NetPeer>, game: Res<PeerGame>) {
+    let message = PeerMessage::Inputs {
+        game: game.number,
+        inputs: game.session.message(),
+    };
+    if let Err(err) = peer.send(&message) {
+        error!("Failed to send to peer: {}", err);
+    }
+}
@@ -0,0 +1,161 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    replay::{Replay, ReplayTick},
+    GameConfig, GameState,
+};
+
+use super::{snake::Snake, GameRng, MovementStages, OnGameStart, Simulation, SpeedUp};
+
+pub struct RecorderPlugin;
+
+impl Plugin for RecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .add_systems(
+                OnGameStart,
+                start_recording
+                    .after(super::on_game_start)
+                    .run_if(not(resource_exists::<super::Attract>)),
+            )
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                save_replay.run_if(super::local_simulation),
+            )
+            .add_systems(
+                OnEnter(GameState::Victory),
+                save_replay.run_if(super::local_simulation),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    play_tick
+                        .in_set(MovementStages::Input)
+                        .run_if(resource_exists::<ReplayPlayer>),
+                    record_tick
+                        .in_set(MovementStages::Calculate)
+                        .before(super::simulation_step)
+                        .run_if(not(resource_exists::<ReplayPlayer>))
+                        .run_if(super::local_simulation),
+                )
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Records inputs of current game
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    /// Where to save replay after game over
+    pub path: Option<PathBuf>,
+    /// Replay of current game
+    pub replay: Replay,
+}
+
+impl ReplayRecorder {
+    pub fn to_file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..default()
+        }
+    }
+}
+
+impl Default for ReplayRecorder {
+    fn default() -> Self {
+        Self {
+            path: None,
+            replay: Replay::new(0, GameConfig::default()),
+        }
+    }
+}
+
+/// Plays recorded game instead of keyboard & gamepad input
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    replay: Replay,
+    cursor: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, cursor: 0 }
+    }
+
+    /// Seed of recorded game
+    pub fn seed(&self) -> u64 {
+        self.replay.seed
+    }
+
+    /// Inputs for next tick
+    pub fn peek(&self) -> Option<&ReplayTick> {
+        self.replay.ticks.get(self.cursor)
+    }
+
+    /// Start playing from the beginning
+    pub fn rewind(&mut self) {
+        self.cursor = 0;
+    }
+}
+
+/// Start new replay for new game
+fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    rng: Res<GameRng>,
+    config: Res<GameConfig>,
+) {
+    recorder.replay = Replay::new(rng.seed(), config.clone());
+}
+
+/// Save inputs of current tick
+fn record_tick(
+    mut recorder: ResMut<ReplayRecorder>,
+    snakes: Query<&Snake>,
+    speed_up: Res<SpeedUp>,
+) {
+    let mut directions = vec![None; snakes.iter().len()];
+    for snake in &snakes {
+        if let Some(direction) = directions.get_mut(snake.index) {
+            *direction = snake.input;
+        }
+    }
+    recorder.replay.ticks.push(ReplayTick {
+        directions,
+        speed_up: speed_up.0,
+    });
+}
+
+/// Pass recorded inputs to snake
+fn play_tick(mut player: ResMut<ReplayPlayer>, mut snakes: Query<&mut Snake>) {
+    let Some(tick) = player.peek().cloned() else {
+        return;
+    };
+    player.cursor += 1;
+    for mut snake in &mut snakes {
+        snake.input = tick.directions.get(snake.index).copied().flatten();
+    }
+}
+
+/// Write replay to file after game over
+fn save_replay(
+    mut recorder: ResMut<ReplayRecorder>,
+    player: Option<Res<ReplayPlayer>>,
+    simulation: Res<Simulation>,
+) {
+    // Do not overwrite replay by itself
+    if player.is_some() {
+        return;
+    }
+    recorder.replay.stats = Some(simulation.0.stats());
+    if let Some(path) = &recorder.path {
+        match recorder.replay.save(path) {
+            Ok(()) => info!("Replay saved to {}", path.display()),
+            Err(err) => error!("Failed to save replay: {}", err),
+        }
+    }
+}
@@ -4,109 +4,118 @@
 use std::collections::VecDeque;
 
 use bevy::prelude::*;
-use bevy_spatial::SpatialAccess;
 
-use crate::{GameState, SNAKE_FAT_STEPS};
+use crate::{
+    bot::{self, BotLevel},
+    config::MAX_PLAYERS,
+    net::{NetClient, NetPeer},
+    sim::{DeathCause, SimEvent, SimOccupant, SnakeDirection},
+    GameConfig, GameState,
+};
 
 use super::{
-    meat::MeatEaten, CollisionTracker, GameTickTimer, Movable, MovementStages, NNTree, PlayerStats,
+    borders::Border,
+    cell_to_world,
+    meat::{Meat, MeatEaten},
+    recorder::ReplayPlayer,
+    stats::SnakeStats,
+    GridPos, MovementStages, OnGameStart, OnGameStop, Simulation, SimulationEvent,
+    SimulationRestored, TickStages,
 };
 pub struct SnakePlugin;
 
 impl Plugin for SnakePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<SnakeSpawnedEvent>()
-            .add_event::<SnakeCollisionEvent>()
+        app.add_event::<SnakeCollisionEvent>()
+            .add_event::<SnakeDeath>()
             .add_event::<SnakeCatastrophicEvent>()
-            .insert_resource(SnakeInputBuffer(VecDeque::new()))
+            .init_resource::<Autopilot>()
             .insert_resource(SnakeAnimationTickTimer(Timer::from_seconds(
                 0.05,
                 TimerMode::Repeating,
             )))
-            .add_systems(OnEnter(GameState::InGame), spawn_snake)
-            .add_systems(OnExit(GameState::InGame), despawn_all_snakes)
+            .add_systems(OnGameStart, spawn_snake.after(super::on_game_start))
+            .add_systems(OnGameStop, despawn_all_snakes)
             .add_systems(
                 Update,
                 (
-                    snake_input.in_set(MovementStages::Input),
-                    (move_snake_head, move_snake_body)
-                        .in_set(MovementStages::Calculate)
-                        .after(MovementStages::Input),
-                    on_snake_spawn,
-                    player_score_collector,
-                    snake_animation_tick_timer,
-                    snake_fat_spread_animation,
                     (
-                        spawn_snake_body,
-                        snake_collision,
-                        snake_collision_with_snakes,
+                        snake_input.run_if(not(resource_exists::<ReplayPlayer>)),
+                        toggle_autopilot,
                     )
-                        .after(MovementStages::Commit),
+                        .run_if(in_state(GameState::InGame)),
+                    (snake_animation_tick_timer, snake_fat_spread_animation)
+                        .run_if(super::game_running),
+                ),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        bot_input.run_if(super::local_simulation),
+                        autopilot_input.run_if(|autopilot: Res<Autopilot>| autopilot.0),
+                        apply_snake_input,
+                    )
+                        .chain()
+                        .in_set(MovementStages::Input)
+                        .run_if(not(resource_exists::<ReplayPlayer>)),
+                    move_snakes.in_set(MovementStages::Commit),
+                    (snake_collision, snake_death)
+                        .chain()
+                        .in_set(TickStages::Events),
+                    (hide_dead_snakes, start_fat_animation).in_set(TickStages::React),
                 )
-                    .run_if(in_state(GameState::InGame)),
+                    .run_if(super::game_running),
             );
     }
 }
 
-#[derive(Debug, Clone)]
-enum SnakeDirection {
-    Up,
-    Right,
-    Down,
-    Left,
-}
-
-impl SnakeDirection {
-    fn get_vector(&self) -> Vec2 {
-        match self {
-            SnakeDirection::Up => Vec2::new(0., 1.),
-            SnakeDirection::Right => Vec2::new(1., 0.),
-            SnakeDirection::Down => Vec2::new(0., -1.),
-            SnakeDirection::Left => Vec2::new(-1., 0.),
-        }
-    }
-
-    fn is_oposite(&self, other: &SnakeDirection) -> bool {
-        let a = self.get_vector();
-        let b = other.get_vector();
-        a.dot(b) == -1.
-    }
-}
-
 /// Snake head component
+///
+/// Contains index of simulated snake and input for next tick
 #[derive(Component)]
-struct Snake(SnakeDirection);
+pub struct Snake {
+    pub index: usize,
+    pub input: Option<SnakeDirection>,
+}
 
-/// Reference on snake head
-///
-/// Used for snake body elements
+/// Computer-controlled snake
 #[derive(Component)]
-struct SnakeRef(Entity);
+pub struct SnakeBot(pub BotLevel);
 
-/// Snake body
-///
-/// Contains next and pervious snake body element
+/// Snake of the first player (own snake in network game) is driven by solver
+#[derive(Resource, Default)]
+pub struct Autopilot(pub bool);
+
+/// Snake body, kept by snake head
 ///
-/// forward <-- HEAD <-- BODY[] <-- TAIL <-- backward
-#[derive(Component)]
+/// Head entity is the first element of snake, `sprites` are other elements
+/// from neck to tail. Sprite stays in its cell, moving snake takes the tail
+/// sprite to the neck cell.
+#[derive(Component, Default)]
 struct SnakeBody {
-    forward: Option<Entity>,
-    backward: Option<Entity>,
+    sprites: VecDeque<Entity>,
+    /// Shown head cell
+    head: IVec2,
+    /// Simulation tick of shown elements, `None` before the first one
+    tick: Option<u64>,
 }
 
-/// Marker for last snake element
+/// Sprite of snake element, head included
 #[derive(Component)]
-struct SnakeTail;
+struct SnakeElement;
 
-// Used for making snake fat spread animation after eating meat
-#[derive(Component)]
-struct SnakeFatAnimator(i32, Color);
-
-/// Called when snake head spawned
+/// Snake fat spread animation after eating meat
 ///
-/// Attention! Snake spawned without SnakeTail & SnakeRef
-#[derive(Event)]
-pub struct SnakeSpawnedEvent(Entity);
+/// Every wave goes one element towards the tail per animation tick, element
+/// gets fat with meat color and slims back in `fat_steps` ticks
+#[derive(Component, Default)]
+struct SnakeFat {
+    /// Animation ticks since meat was eaten and meat color, newest last
+    waves: Vec<(i32, Color)>,
+    /// Elements painted by last animation tick
+    painted: Vec<Entity>,
+}
 
 /// Called when snake collides with other entity
 #[derive(Event)]
@@ -114,303 +123,456 @@ pub struct SnakeCollisionEvent {
     pub snake: Entity,
     pub other: Entity,
     pub position: Vec2,
+    /// Board cell of collision
+    pub cell: IVec2,
 }
 
-/// Store inputs and apply them in next frame
-#[derive(Resource)]
+/// Store inputs of snake and apply them in next ticks
+#[derive(Component, Default)]
 struct SnakeInputBuffer(VecDeque<SnakeDirection>);
 
 /// Timer for snake fat animation
 #[derive(Resource)]
 struct SnakeAnimationTickTimer(Timer);
 
-/// Called when snake collides with other snake
+/// Called when snake dies
 #[derive(Event)]
-pub struct SnakeCatastrophicEvent(Entity);
-
-/// Spawn snake head
-fn spawn_snake(mut ev_snake_spawned: EventWriter<SnakeSpawnedEvent>, mut commands: Commands) {
-    // Spawn snake
-    let snake = commands.spawn((
-        Snake(SnakeDirection::Right),
-        SnakeBody {
-            forward: None,
-            backward: None,
-        },
-        Movable(None),
-        SpriteBundle {
-            sprite: Sprite {
-                color: Color::rgb(1., 1., 1.),
-                custom_size: Vec2::new(1., 1.).into(),
-                ..default()
-            },
-            ..default()
-        },
-        CollisionTracker,
-    ));
+pub struct SnakeDeath {
+    pub victim: Entity,
+    /// Snake credited for the kill
+    pub killer: Option<Entity>,
+    pub cause: DeathCause,
+}
 
-    ev_snake_spawned.send(SnakeSpawnedEvent(snake.id()));
+/// Called when snake dies, same as `SnakeDeath` without details
+#[derive(Event)]
+pub struct SnakeCatastrophicEvent(pub Entity);
+
+/// Colors of player snakes
+const PLAYER_COLORS: [Color; MAX_PLAYERS] = [
+    Color::rgb(1., 1., 1.),
+    Color::rgb(0.3, 0.8, 1.),
+    Color::rgb(1., 0.85, 0.2),
+    Color::rgb(1., 0.4, 0.8),
+];
+
+/// Keyboard bindings of players in order of `DIRECTIONS`
+const PLAYER_KEYS: [[KeyCode; 4]; 2] = [
+    [KeyCode::KeyW, KeyCode::KeyS, KeyCode::KeyD, KeyCode::KeyA],
+    [
+        KeyCode::ArrowUp,
+        KeyCode::ArrowDown,
+        KeyCode::ArrowRight,
+        KeyCode::ArrowLeft,
+    ],
+];
+
+/// Gamepad bindings in order of `DIRECTIONS`
+const PLAYER_BUTTONS: [GamepadButtonType; 4] = [
+    GamepadButtonType::DPadUp,
+    GamepadButtonType::DPadDown,
+    GamepadButtonType::DPadRight,
+    GamepadButtonType::DPadLeft,
+];
+
+const DIRECTIONS: [SnakeDirection; 4] = [
+    SnakeDirection::Up,
+    SnakeDirection::Down,
+    SnakeDirection::Right,
+    SnakeDirection::Left,
+];
+
+/// Color of snake by player index
+pub fn player_color(index: usize) -> Color {
+    PLAYER_COLORS[index % MAX_PLAYERS]
 }
 
-/// Called for snake after snake spawn
-fn on_snake_spawn(
-    mut ev_snake_spawned: EventReader<SnakeSpawnedEvent>,
-    snakes: Query<Entity, With<Snake>>,
-    mut commands: Commands,
-) {
-    for ev in ev_snake_spawned.read() {
-        // Search snake
-        let snake = snakes.get(ev.0).unwrap();
-        // Add reference to snake head and tail component for new snake
-        commands.entity(snake).insert((SnakeRef(snake), SnakeTail));
-        debug!("Setup snake {:?} after spawn", snake);
+/// Sprite of snake element
+fn snake_sprite(index: usize) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color: player_color(index),
+            custom_size: Vec2::new(1., 1.).into(),
+            ..default()
+        },
+        ..default()
     }
 }
 
-/// Calculate movement of snake head
-fn move_snake_head(
-    mut snakes: Query<(&mut Movable, &Transform, &Snake)>,
-    timer: ResMut<GameTickTimer>,
-) {
-    if timer.0.just_finished() {
-        for (mut movable, transform, snake) in &mut snakes {
-            let current_pos = transform.translation.truncate();
-            let next_pos = snake.0.get_vector() + current_pos;
-            movable.0 = Some(next_pos);
+/// Spawn snake heads for simulated snakes
+fn spawn_snake(mut commands: Commands, simulation: Res<Simulation>, config: Res<GameConfig>) {
+    for (index, sim_snake) in simulation.0.snakes.iter().enumerate() {
+        let mut snake = commands.spawn((
+            Snake { index, input: None },
+            SnakeInputBuffer::default(),
+            SnakeStats::default(),
+            SnakeBody::default(),
+            SnakeFat::default(),
+            SnakeElement,
+            GridPos(sim_snake.head()),
+            snake_sprite(index),
+        ));
+        if let Some(level) = config.bot(index) {
+            snake.insert(SnakeBot(level));
         }
+        debug!("Spawn snake {:?}", snake.id());
     }
 }
 
-/// Calculate movement of snake body elements
-fn move_snake_body(
-    mut movable_bodies: Query<(&mut Movable, &SnakeBody), Without<Snake>>,
-    bodies: Query<&Transform, With<SnakeBody>>,
-    timer: ResMut<GameTickTimer>,
-    mut stats: ResMut<PlayerStats>,
+/// Spawn sprite of snake body element
+fn spawn_element(commands: &mut Commands, index: usize, pos: IVec2) -> Entity {
+    commands
+        .spawn((SnakeElement, GridPos(pos), snake_sprite(index)))
+        .id()
+}
+
+/// Apply positions of alive simulated snakes to snake elements
+///
+/// Snake moved by one cell in the next tick only takes its tail sprite (or a
+/// new one, when snake grows) to the neck cell. Any other change, like
+/// restored or skipped ticks, places every element again.
+fn move_snakes(
+    mut commands: Commands,
+    simulation: Res<Simulation>,
+    mut ev_restored: EventReader<SimulationRestored>,
+    mut snakes: Query<(&Snake, &mut SnakeBody, &mut GridPos)>,
+    mut elements: Query<&mut GridPos, (With<SnakeElement>, Without<Snake>)>,
 ) {
-    if timer.0.just_finished() {
-        for (mut movable, body) in &mut movable_bodies {
-            if let Some(next_body) = body.forward {
-                let next_pos = bodies.get(next_body).unwrap().translation;
-                movable.0 = Some(next_pos.truncate());
+    let restored = ev_restored.read().count() > 0;
+    let tick = simulation.0.tick;
+    for (snake, mut body, mut head) in &mut snakes {
+        let Some(sim_snake) = simulation.0.snakes.get(snake.index) else {
+            continue;
+        };
+        if !sim_snake.alive || (!restored && body.tick == Some(tick)) {
+            continue;
+        }
+        head.set_if_neq(GridPos(sim_snake.head()));
+        let length = sim_snake.body.len();
+        let shown = body.sprites.len() + 1;
+        let moved = !restored
+            && body.tick.map(|last| last + 1) == Some(tick)
+            && (length == shown || length == shown + 1)
+            && sim_snake.body.get(1) == Some(&body.head);
+        body.head = sim_snake.head();
+        body.tick = Some(tick);
+
+        if moved {
+            // Last of sprites left behind goes to the neck
+            let neck = sim_snake.body[1];
+            let mut spare = None;
+            while body.sprites.len() > length - 2 {
+                if let Some(extra) = std::mem::replace(&mut spare, body.sprites.pop_back()) {
+                    commands.entity(extra).despawn();
+                }
+            }
+            let sprite = match spare.map(|sprite| (sprite, elements.get_mut(sprite))) {
+                Some((sprite, Ok(mut pos))) => {
+                    *pos = GridPos(neck);
+                    sprite
+                }
+                _ => spawn_element(&mut commands, snake.index, neck),
+            };
+            body.sprites.push_front(sprite);
+            continue;
+        }
+
+        // Remove extra elements, move the rest and spawn missing ones
+        while body.sprites.len() > length.saturating_sub(1) {
+            if let Some(extra) = body.sprites.pop_back() {
+                commands.entity(extra).despawn();
+            }
+        }
+        for (sprite, cell) in body.sprites.iter().zip(sim_snake.body.range(1..)) {
+            if let Ok(mut pos) = elements.get_mut(*sprite) {
+                pos.set_if_neq(GridPos(*cell));
             }
         }
-        // Update stats
-        stats.distance_traveled += 1;
+        for cell in sim_snake.body.range(body.sprites.len() + 1..) {
+            let sprite = spawn_element(&mut commands, snake.index, *cell);
+            body.sprites.push_back(sprite);
+        }
     }
 }
 
 /// Player input handler
+///
+/// Single player (or own snake in network game) can use every binding,
+/// otherwise player N gets N-th keyboard binding and N-th gamepad
 fn snake_input(
-    mut snakes: Query<&mut Snake>,
     keys: Res<ButtonInput<KeyCode>>,
     gamepads: Res<Gamepads>,
     gamepad_inputs: Res<ButtonInput<GamepadButton>>,
-    mut buffer: ResMut<SnakeInputBuffer>,
-    timer: ResMut<GameTickTimer>,
+    client: Option<Res<NetClient>>,
+    peer: Option<Res<NetPeer>>,
+    mut snakes: Query<(&Snake, &mut SnakeInputBuffer), Without<SnakeBot>>,
 ) {
-    // Get input
-    let mut direction: Option<SnakeDirection> = None;
-    if keys.any_just_pressed([KeyCode::ArrowUp, KeyCode::KeyW]) {
-        direction = SnakeDirection::Up.into();
-    }
-    if keys.any_just_pressed([KeyCode::ArrowDown, KeyCode::KeyS]) {
-        direction = SnakeDirection::Down.into();
+    let single = snakes.iter().len() == 1;
+    let own = own_snake(client, peer);
+    // Gamepads in order of connection
+    let mut pads: Vec<Gamepad> = gamepads.iter().collect();
+    pads.sort_by_key(|gamepad| gamepad.id);
+
+    for (snake, mut buffer) in &mut snakes {
+        let (key_sets, pads) = if single || own == Some(snake.index) {
+            (&PLAYER_KEYS[..], &pads[..])
+        } else if own.is_some() {
+            // Other snakes are controlled over network
+            continue;
+        } else {
+            let player = snake.index..snake.index + 1;
+            (
+                PLAYER_KEYS.get(player.clone()).unwrap_or_default(),
+                pads.get(player).unwrap_or_default(),
+            )
+        };
+
+        // Get input
+        let mut direction: Option<SnakeDirection> = None;
+        for (binding, next) in DIRECTIONS.iter().enumerate() {
+            let key_pressed = key_sets
+                .iter()
+                .any(|key_set| keys.just_pressed(key_set[binding]));
+            let pad_pressed = pads.iter().any(|pad| {
+                gamepad_inputs.just_pressed(GamepadButton::new(*pad, PLAYER_BUTTONS[binding]))
+            });
+            if key_pressed || pad_pressed {
+                direction = Some(*next);
+            }
+        }
+
+        // Save input to buffer
+        if buffer.0.len() < 2 {
+            if let Some(direction) = direction {
+                buffer.0.push_back(direction);
+            }
+        }
     }
-    if keys.any_just_pressed([KeyCode::ArrowRight, KeyCode::KeyD]) {
-        direction = SnakeDirection::Right.into();
+}
+
+/// Snake of this player in network game
+fn own_snake(client: Option<Res<NetClient>>, peer: Option<Res<NetPeer>>) -> Option<usize> {
+    client
+        .map(|client| client.player)
+        .or(peer.map(|peer| peer.player))
+}
+
+/// Turn autopilot on and off
+fn toggle_autopilot(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_inputs: Res<ButtonInput<GamepadButton>>,
+    mut autopilot: ResMut<Autopilot>,
+) {
+    let pad_pressed = gamepads.iter().any(|gamepad| {
+        gamepad_inputs.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Select))
+    });
+    if keys.just_pressed(KeyCode::Tab) || pad_pressed {
+        autopilot.0 = !autopilot.0;
+        info!("Autopilot {}", if autopilot.0 { "on" } else { "off" });
     }
-    if keys.any_just_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]) {
-        direction = SnakeDirection::Left.into();
+}
+
+/// Autopilot handler, solver writes to input buffer like a player
+///
+/// Decides once per simulated tick, network game may run several game ticks
+/// per simulated one
+fn autopilot_input(
+    simulation: Res<Simulation>,
+    client: Option<Res<NetClient>>,
+    peer: Option<Res<NetPeer>>,
+    mut snakes: Query<(&Snake, &mut SnakeInputBuffer), Without<SnakeBot>>,
+    mut decided: Local<Option<u64>>,
+) {
+    if *decided == Some(simulation.0.tick) {
+        return;
     }
+    *decided = Some(simulation.0.tick);
 
-    // Get gamepad input
-    for gamepad in gamepads.iter() {
-        if gamepad_inputs.any_just_pressed([GamepadButton::new(gamepad, GamepadButtonType::DPadUp)])
-        {
-            direction = SnakeDirection::Up.into();
-        }
-        if gamepad_inputs
-            .any_just_pressed([GamepadButton::new(gamepad, GamepadButtonType::DPadDown)])
-        {
-            direction = SnakeDirection::Down.into();
-        }
-        if gamepad_inputs
-            .any_just_pressed([GamepadButton::new(gamepad, GamepadButtonType::DPadRight)])
-        {
-            direction = SnakeDirection::Right.into();
+    // Other local players keep their controls
+    let own = own_snake(client, peer).unwrap_or(0);
+    for (snake, mut buffer) in &mut snakes {
+        if snake.index != own || !buffer.0.is_empty() {
+            continue;
         }
-        if gamepad_inputs
-            .any_just_pressed([GamepadButton::new(gamepad, GamepadButtonType::DPadLeft)])
-        {
-            direction = SnakeDirection::Left.into();
+        if let Some(direction) = bot::autopilot(&simulation.0, snake.index) {
+            buffer.0.push_back(direction);
         }
     }
+}
 
-    // Save input to buffer
-    if buffer.0.len() < 2 {
-        if let Some(direction) = direction {
-            buffer.0.push_back(direction.clone());
+/// Bot input handler, bots decide once per game tick
+fn bot_input(
+    simulation: Res<Simulation>,
+    mut bots: Query<(&Snake, &SnakeBot, &mut SnakeInputBuffer)>,
+) {
+    for (snake, bot, mut buffer) in &mut bots {
+        if !buffer.0.is_empty() {
+            continue;
+        }
+        if let Some(direction) = bot::think(&simulation.0, snake.index, bot.0) {
+            buffer.0.push_back(direction);
         }
     }
+}
 
-    // Apply movement from buffer
-    if timer.0.just_finished() {
+/// Pass input from buffers to simulation, one per game tick
+fn apply_snake_input(mut snakes: Query<(&mut Snake, &mut SnakeInputBuffer)>) {
+    for (mut snake, mut buffer) in &mut snakes {
         if let Some(direction) = buffer.0.pop_front() {
-            for mut snake in &mut snakes {
-                if !snake.0.is_oposite(&direction) {
-                    snake.0 = direction.clone();
-                }
-            }
+            snake.input = Some(direction);
         }
     }
 }
 
-/// Creates new snake body element
-fn spawn_snake_body(
+/// Hide dead snakes
+///
+/// Body elements of dead snake are removed, head is only hidden, it keeps
+/// snake statistics till the end of game.
+fn hide_dead_snakes(
     mut commands: Commands,
-    mut ev_meat_eaten: EventReader<MeatEaten>,
-    mut tails: Query<
-        (
-            Entity,
-            &mut SnakeBody,
-            &SnakeRef,
-            Option<&Snake>,
-            &Transform,
-        ),
-        With<SnakeTail>,
-    >,
-    bodies: Query<&Transform, (With<SnakeBody>, Without<SnakeTail>)>,
+    mut heads: Query<(&Snake, &mut SnakeBody, &mut SnakeFat, &mut Visibility)>,
+    simulation: Res<Simulation>,
 ) {
-    for ev in ev_meat_eaten.read() {
-        for (entity, mut body, snake_ref, snake, transform) in &mut tails {
-            // Check what snake requested
-            if ev.snake != snake_ref.0 {
-                continue;
-            }
-
-            // Get current position
-            let current_pos = transform.translation;
-
-            // Get next snake body entity
-            let delta;
-            if let Some(next_body) = body.forward {
-                // Search transformation component for next snake body
-                let next_body_pos = bodies.get(next_body).unwrap().translation;
-                // Calculate delta of current position and next position
-                delta = current_pos - next_body_pos;
-            } else if let Some(snake) = snake {
-                // Get delta from direction of snake head moving
-                delta = snake.0.get_vector().extend(0.) * Vec3::new(-1., -1., -1.);
-            } else {
-                continue;
-            }
-            // Calculate new position for tail
-            let new_pos = current_pos + delta;
-
-            // Spawn new snake tail
-            let tail = commands.spawn((
-                SnakeBody {
-                    forward: Some(entity),
-                    backward: None,
-                },
-                Movable(None),
-                SnakeRef(snake_ref.0),
-                SnakeTail,
-                SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::rgb(1., 1., 1.),
-                        custom_size: Vec2::new(1., 1.).into(),
-                        ..default()
-                    },
-                    transform: Transform {
-                        translation: new_pos,
-                        ..default()
-                    },
-                    ..default()
-                },
-                CollisionTracker,
-            ));
-
-            debug!("Snake new tail! {:?}, {:?}", tail.id(), new_pos);
-
-            // Set backward for pervious body
-            body.backward = Some(tail.id());
-
-            // Remove snake tail from pervious body
-            commands.entity(entity).remove::<SnakeTail>();
-
-            // Add animator for snake head
-            commands
-                .entity(snake_ref.0)
-                .insert(SnakeFatAnimator(SNAKE_FAT_STEPS, ev.color));
+    for (snake, mut body, mut fat, mut visibility) in &mut heads {
+        let Some(sim_snake) = simulation.0.snakes.get(snake.index) else {
+            continue;
+        };
+        if sim_snake.alive {
+            visibility.set_if_neq(Visibility::Inherited);
+            continue;
         }
+        visibility.set_if_neq(Visibility::Hidden);
+        if body.tick.is_none() {
+            continue;
+        }
+        for sprite in body.sprites.drain(..) {
+            commands.entity(sprite).despawn();
+        }
+        body.tick = None;
+        fat.waves.clear();
     }
 }
 
-fn player_score_collector(
+/// Start fat animation of snake which ate meat
+fn start_fat_animation(
     mut ev_meat_eaten: EventReader<MeatEaten>,
-    mut stats: ResMut<PlayerStats>,
+    mut snakes: Query<&mut SnakeFat>,
 ) {
-    for _ in ev_meat_eaten.read() {
-        stats.score += 50;
-        stats.food_eaten += 1;
+    for ev in ev_meat_eaten.read() {
+        if let Ok(mut fat) = snakes.get_mut(ev.snake) {
+            fat.waves.push((0, ev.color));
+        }
     }
 }
 
 /// Remove snake head and snake elements
-fn despawn_all_snakes(mut commands: Commands, query: Query<Entity, With<SnakeBody>>) {
+fn despawn_all_snakes(mut commands: Commands, query: Query<Entity, With<SnakeElement>>) {
     for entity in query.iter() {
         commands.entity(entity).despawn();
     }
 }
 
-/// Check snake collision
+/// Search snake head entity by simulated snake index
+fn find_snake(snakes: &Query<(Entity, &Snake)>, index: usize) -> Option<Entity> {
+    snakes
+        .iter()
+        .find(|(_, snake)| snake.index == index)
+        .map(|(entity, _)| entity)
+}
+
+/// Translate simulation collisions to entities
 fn snake_collision(
+    mut ev_simulation: EventReader<SimulationEvent>,
     mut ev_snake_collision: EventWriter<SnakeCollisionEvent>,
-    timer: ResMut<GameTickTimer>,
-    tree: Res<NNTree>,
-    query: Query<(Entity, &Transform), With<Snake>>,
+    simulation: Res<Simulation>,
+    snakes: Query<(Entity, &Snake)>,
+    bodies: Query<&SnakeBody>,
+    meats: Query<(Entity, &Meat)>,
+    borders: Query<(Entity, &Border)>,
 ) {
-    if timer.0.just_finished() {
-        // Run search for all snakes (heads)
-        for (entity, transform) in query.iter() {
-            // Get snake position
-            let pos = transform.translation.truncate();
-            // Find nearest entities
-            for (other_pos, other_entity) in tree.within_distance(pos, 1.) {
-                // Is entity is available
-                if let Some(other_entity) = other_entity {
-                    // Do not react on self & react only on same position
-                    if entity != other_entity && pos == other_pos {
-                        // Send collision event
-                        ev_snake_collision.send(SnakeCollisionEvent {
-                            snake: entity,
-                            other: other_entity,
-                            position: pos,
-                        });
-                    }
-                }
+    for ev in ev_simulation.read() {
+        let SimEvent::SnakeCollision {
+            snake,
+            other,
+            position,
+        } = ev.0
+        else {
+            continue;
+        };
+        let Some(snake) = find_snake(&snakes, snake) else {
+            continue;
+        };
+        // Search entity of other element
+        let other = match other {
+            SimOccupant::Snake { snake, segment } => {
+                find_snake(&snakes, snake).and_then(|head| match segment {
+                    0 => Some(head),
+                    _ => bodies
+                        .get(head)
+                        .ok()
+                        .and_then(|body| body.sprites.get(segment - 1).copied()),
+                })
             }
-        }
+            SimOccupant::Meat { id } => meats
+                .iter()
+                .find(|(_, meat)| meat.0 == id)
+                .map(|(entity, _)| entity),
+            // Any crossed border in the corner
+            SimOccupant::Wall { side } => borders
+                .iter()
+                .find(|(_, border)| border.0.dot(side) == 1)
+                .map(|(entity, _)| entity),
+        };
+        let Some(other) = other else {
+            continue;
+        };
+
+        let ev = SnakeCollisionEvent {
+            snake,
+            other,
+            position: cell_to_world(position, simulation.0.rules.map_size).truncate(),
+            cell: position,
+        };
+        debug!(
+            "Snake {:?} collision with {:?} at {:?}",
+            ev.snake, ev.other, ev.cell
+        );
+        ev_snake_collision.send(ev);
     }
 }
 
-fn snake_collision_with_snakes(
-    mut ev_snake_collision: EventReader<SnakeCollisionEvent>,
+/// Translate simulation deaths to entities
+fn snake_death(
+    mut ev_simulation: EventReader<SimulationEvent>,
+    mut ev_snake_death: EventWriter<SnakeDeath>,
     mut ev_snake_catastrophic: EventWriter<SnakeCatastrophicEvent>,
-    bodies: Query<(Entity, &Transform), (With<SnakeBody>, With<CollisionTracker>)>,
+    snakes: Query<(Entity, &Snake)>,
 ) {
-    for ev in ev_snake_collision.read() {
-        // Check is other_entity is SnakeBody
-        if bodies.contains(ev.other) {
-            // Send event
-            ev_snake_catastrophic.send(SnakeCatastrophicEvent(ev.snake));
-            debug!(
-                "Snake {:?} collision with snake body {:?} detected at {:?}",
-                ev.snake, ev.position, ev.other
-            );
-        }
+    for ev in ev_simulation.read() {
+        let SimEvent::SnakeDied {
+            snake,
+            position,
+            cause,
+            killer,
+        } = ev.0
+        else {
+            continue;
+        };
+        let Some(victim) = find_snake(&snakes, snake) else {
+            continue;
+        };
+        let killer = killer.and_then(|killer| find_snake(&snakes, killer));
+        debug!(
+            "Snake {:?} died ({}) at {:?}, killer {:?}",
+            victim, cause, position, killer
+        );
+        ev_snake_catastrophic.send(SnakeCatastrophicEvent(victim));
+        ev_snake_death.send(SnakeDeath {
+            victim,
+            killer,
+            cause,
+        });
     }
 }
 
@@ -420,51 +582,74 @@ fn snake_animation_tick_timer(time: Res<Time>, mut timer: ResMut<SnakeAnimationT
 }
 
 fn snake_fat_spread_animation(
-    mut commands: Commands,
-    mut query: Query<
-        (
-            Entity,
-            &SnakeBody,
-            &mut Transform,
-            &mut Sprite,
-            &mut SnakeFatAnimator,
-        ),
-        With<SnakeFatAnimator>,
-    >,
-    timer: ResMut<SnakeAnimationTickTimer>,
+    mut snakes: Query<(Entity, &Snake, &SnakeBody, &mut SnakeFat)>,
+    mut sprites: Query<(&mut Transform, &mut Sprite)>,
+    timer: Res<SnakeAnimationTickTimer>,
+    config: Res<GameConfig>,
 ) {
-    if timer.0.just_finished() {
-        for (entity, body, mut transform, mut sprite, mut animator) in &mut query {
-            // Spread fat animator
-            if animator.0 >= SNAKE_FAT_STEPS {
-                if let Some(backward) = body.backward {
-                    commands
-                        .entity(backward)
-                        .insert(SnakeFatAnimator(SNAKE_FAT_STEPS, animator.1));
-                }
+    if !timer.0.just_finished() {
+        return;
+    }
+    let fat_steps = config.fat_steps;
+    for (head, snake, body, mut fat) in &mut snakes {
+        let fat = &mut *fat;
+        let color = player_color(snake.index);
+
+        // Slim elements of last tick, waves make them fat again
+        for entity in fat.painted.drain(..) {
+            if let Ok((mut transform, mut sprite)) = sprites.get_mut(entity) {
+                paint_fat(&mut transform, &mut sprite, 0., color, color);
             }
+        }
 
-            // Change zoom
-            let zoom = 1. + ((animator.0 as f32 / SNAKE_FAT_STEPS as f32) / 2.);
-            transform.scale.x = zoom;
-            transform.scale.y = zoom;
-
-            // Change color
-            let lightness =
-                0.4 + (0.6 * ((SNAKE_FAT_STEPS - animator.0) as f32 / SNAKE_FAT_STEPS as f32));
-            sprite.color.set_h(animator.1.h());
-            sprite.color.set_s(animator.1.s());
-            sprite.color.set_l(lightness);
-            sprite.color.set_a(1.);
-
-            // Remove animator from body, if step is 0
-            if animator.0 <= 0 {
-                commands.entity(entity).remove::<SnakeFatAnimator>();
-                continue;
+        // Older waves first, newer wave covers them
+        let length = body.sprites.len() as i32 + 1;
+        for (age, meat_color) in &fat.waves {
+            for index in (age - fat_steps).max(0)..=(*age).min(length - 1) {
+                let element = match index {
+                    0 => Some(head),
+                    _ => body.sprites.get(index as usize - 1).copied(),
+                };
+                let Some(entity) = element else {
+                    continue;
+                };
+                let Ok((mut transform, mut sprite)) = sprites.get_mut(entity) else {
+                    continue;
+                };
+                let step = fat_steps - (age - index);
+                let amount = step as f32 / fat_steps as f32;
+                paint_fat(&mut transform, &mut sprite, amount, *meat_color, color);
+                fat.painted.push(entity);
             }
+        }
 
-            // Step down animator
-            animator.0 -= 1;
+        // Move waves towards the tail
+        for (age, _) in &mut fat.waves {
+            *age += 1;
         }
+        fat.waves.retain(|(age, _)| age - fat_steps < length);
     }
 }
+
+/// Paint element by amount of fat, from 0 (slim) to 1
+fn paint_fat(
+    transform: &mut Transform,
+    sprite: &mut Sprite,
+    amount: f32,
+    meat: Color,
+    color: Color,
+) {
+    // Change zoom
+    let zoom = 1. + amount / 2.;
+    transform.scale.x = zoom;
+    transform.scale.y = zoom;
+
+    // Change color from meat color back to player color
+    let [r, g, b, _] = color.as_rgba_f32();
+    let [meat_r, meat_g, meat_b, _] = meat.as_rgba_f32();
+    sprite.color = Color::rgb(
+        r + (meat_r - r) * amount,
+        g + (meat_g - g) * amount,
+        b + (meat_b - b) * amount,
+    );
+}
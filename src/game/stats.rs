@@ -0,0 +1,122 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{sim::DeathCause, GameState};
+
+use super::{snake::Snake, Attract, OnGameStart, OnGameStop, Simulation, TickStages};
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameResults>()
+            .add_systems(
+                OnGameStart,
+                reset_results.run_if(not(resource_exists::<Attract>)),
+            )
+            .add_systems(
+                OnGameStop,
+                collect_results.run_if(not(resource_exists::<Attract>)),
+            )
+            .add_systems(
+                Update,
+                (game_time, snake_time_alive).run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                FixedUpdate,
+                update_snake_stats
+                    .in_set(TickStages::Events)
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Statistics of one snake, stored on snake head
+#[derive(Component, Debug, Clone, Default)]
+pub struct SnakeStats {
+    pub score: u32,
+    pub food_eaten: u32,
+    pub distance_traveled: u32,
+    /// Longest length of the snake
+    pub max_length: usize,
+    /// Time from game start till death
+    pub time_alive: Duration,
+    /// Snakes killed by this snake
+    pub kills: u32,
+    /// Why snake died, `None` while alive
+    pub death: Option<DeathCause>,
+    /// Index of snake credited for the kill
+    pub killer: Option<usize>,
+}
+
+/// Results table of the last game
+#[derive(Resource, Debug, Clone, Default)]
+pub struct GameResults {
+    /// Statistics of every snake by index
+    pub snakes: Vec<SnakeStats>,
+    /// Time spent in game without pauses
+    pub time: Duration,
+    /// Last alive snake of game with several snakes
+    pub winner: Option<usize>,
+}
+
+/// Old name of [`GameResults`], resource of the game is the same
+#[deprecated(note = "use `GameResults`, statistics of every snake are `SnakeStats`")]
+pub type PlayerStats = GameResults;
+
+fn reset_results(mut results: ResMut<GameResults>) {
+    *results = GameResults::default();
+}
+
+/// Count time of current game
+fn game_time(time: Res<Time>, mut results: ResMut<GameResults>) {
+    results.time += time.delta();
+}
+
+/// Count time of alive snakes
+fn snake_time_alive(time: Res<Time>, mut query: Query<&mut SnakeStats>) {
+    for mut stats in &mut query {
+        if stats.death.is_none() {
+            stats.time_alive += time.delta();
+        }
+    }
+}
+
+/// Copy simulated statistics to snake heads
+fn update_snake_stats(mut query: Query<(&Snake, &mut SnakeStats)>, simulation: Res<Simulation>) {
+    for (snake, mut stats) in &mut query {
+        let Some(sim_snake) = simulation.0.snakes.get(snake.index) else {
+            continue;
+        };
+        stats.score = sim_snake.stats.score;
+        stats.food_eaten = sim_snake.stats.food_eaten;
+        stats.distance_traveled = sim_snake.stats.distance_traveled;
+        stats.kills = sim_snake.stats.kills;
+        // Snake never gets shorter
+        stats.max_length = sim_snake.body.len();
+        stats.death = sim_snake.death;
+        stats.killer = sim_snake.killer;
+    }
+}
+
+/// Collect statistics of all snakes to results table
+fn collect_results(mut results: ResMut<GameResults>, query: Query<(&Snake, &SnakeStats)>) {
+    let mut snakes: Vec<_> = query.iter().collect();
+    snakes.sort_by_key(|(snake, _)| snake.index);
+    results.snakes = snakes.into_iter().map(|(_, stats)| stats.clone()).collect();
+
+    // Last alive snake wins game with several snakes
+    let mut alive = results
+        .snakes
+        .iter()
+        .enumerate()
+        .filter(|(_, stats)| stats.death.is_none());
+    results.winner = match (alive.next(), alive.next()) {
+        (Some((index, _)), None) if results.snakes.len() > 1 => Some(index),
+        _ => None,
+    };
+}
@@ -0,0 +1,642 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Gym-style environment for training agents
+//!
+//! Agent plays snake 0 of the headless [`SimState`], other snakes are bots
+//! from [`GameConfig::bots`]. Nothing is rendered, so many environments may
+//! run side by side, see [`VecEnv`].
+
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+};
+
+use bevy::math::IVec2;
+
+use crate::{
+    bot,
+    config::ConfigError,
+    sim::{BorderMode, DeathCause, SimEvent, SimState, SnakeDirection},
+    GameConfig, GameRng,
+};
+
+/// Index of snake played by agent
+pub const AGENT: usize = 0;
+
+/// Move of agent for one tick
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Keep current direction
+    #[default]
+    Keep,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Action {
+    /// Count of actions, for discrete action space
+    pub const COUNT: usize = 5;
+
+    /// Action by its number in discrete action space
+    pub fn from_index(index: usize) -> Option<Self> {
+        [
+            Action::Keep,
+            Action::Up,
+            Action::Down,
+            Action::Left,
+            Action::Right,
+        ]
+        .get(index)
+        .copied()
+    }
+
+    /// Input of the snake
+    pub fn direction(self) -> Option<SnakeDirection> {
+        match self {
+            Action::Keep => None,
+            Action::Up => Some(SnakeDirection::Up),
+            Action::Down => Some(SnakeDirection::Down),
+            Action::Left => Some(SnakeDirection::Left),
+            Action::Right => Some(SnakeDirection::Right),
+        }
+    }
+}
+
+/// Layer of observation grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// Head of agent snake
+    Head,
+    /// Every other element of alive snakes, including heads of other snakes
+    Body,
+    Food,
+    /// Solid map edge
+    Wall,
+}
+
+impl Channel {
+    /// Count of channels in observation
+    pub const COUNT: usize = 4;
+}
+
+/// Board as seen by agent
+///
+/// Grid has one cell of border around the board. Border shows walls of solid
+/// map, on wrapped map it repeats cells from the other side of the board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    /// Grid width, board width + 2
+    pub width: usize,
+    /// Grid height, board height + 2
+    pub height: usize,
+    /// Values by channel, row & column, 1 when cell has the thing
+    pub data: Vec<f32>,
+}
+
+impl Observation {
+    /// Observation of the board for `snake`
+    pub fn new(state: &SimState, snake: usize) -> Self {
+        let size = state.rules.map_size;
+        let mut observation = Self {
+            width: size.x as usize + 2,
+            height: size.y as usize + 2,
+            data: vec![0.; Channel::COUNT * (size.x as usize + 2) * (size.y as usize + 2)],
+        };
+        let wrap = state.rules.border_mode == BorderMode::Wrap;
+
+        for (index, sim_snake) in state.snakes.iter().enumerate() {
+            if !sim_snake.alive {
+                continue;
+            }
+            for (segment, pos) in sim_snake.body.iter().enumerate() {
+                let channel = if index == snake && segment == 0 {
+                    Channel::Head
+                } else {
+                    Channel::Body
+                };
+                observation.mark(channel, *pos, size, wrap);
+            }
+        }
+        for meat in &state.meats {
+            observation.mark(Channel::Food, meat.position, size, wrap);
+        }
+        if !wrap {
+            for y in 0..observation.height {
+                for x in 0..observation.width {
+                    if x == 0 || y == 0 || x == observation.width - 1 || y == observation.height - 1
+                    {
+                        observation.set(Channel::Wall, x, y);
+                    }
+                }
+            }
+        }
+        observation
+    }
+
+    /// Dimensions of `data`: channels, rows & columns
+    pub fn shape(&self) -> [usize; 3] {
+        [Channel::COUNT, self.height, self.width]
+    }
+
+    /// Value of grid cell, board cell `(x, y)` is grid cell `(x + 1, y + 1)`
+    pub fn get(&self, channel: Channel, x: usize, y: usize) -> f32 {
+        self.data[self.index(channel, x, y)]
+    }
+
+    fn index(&self, channel: Channel, x: usize, y: usize) -> usize {
+        (channel as usize * self.height + y) * self.width + x
+    }
+
+    fn set(&mut self, channel: Channel, x: usize, y: usize) {
+        let index = self.index(channel, x, y);
+        self.data[index] = 1.;
+    }
+
+    /// Mark board cell, and its copies on the border of wrapped map
+    fn mark(&mut self, channel: Channel, cell: IVec2, size: IVec2, wrap: bool) {
+        // Grid positions of cell along one axis
+        let copies = |value: i32, size: i32| {
+            let mut copies = vec![value as usize + 1];
+            if wrap && value == 0 {
+                copies.push(size as usize + 1);
+            }
+            if wrap && value == size - 1 {
+                copies.push(0);
+            }
+            copies
+        };
+        for y in copies(cell.y, size.y) {
+            for x in copies(cell.x, size.x) {
+                self.set(channel, x, y);
+            }
+        }
+    }
+}
+
+/// Rewards & limits of environment
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvOptions {
+    /// Reward for eaten meat
+    pub meat_reward: f32,
+    /// Reward for death of agent snake
+    pub death_reward: f32,
+    /// Reward for every tick
+    pub tick_reward: f32,
+    /// Episode is truncated after this tick, 0 disables the limit
+    pub max_ticks: u64,
+}
+
+impl Default for EnvOptions {
+    fn default() -> Self {
+        Self {
+            meat_reward: 1.,
+            death_reward: -1.,
+            tick_reward: 0.,
+            max_ticks: 10_000,
+        }
+    }
+}
+
+/// What happened during step
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepInfo {
+    pub tick: u64,
+    pub score: u32,
+    /// Length of agent snake
+    pub length: usize,
+    /// Why agent snake died
+    pub death: Option<DeathCause>,
+    /// Episode hit `max_ticks`, not the end of the game
+    pub truncated: bool,
+    /// Events of the tick
+    pub events: Vec<SimEvent>,
+}
+
+/// Environment with one game
+#[derive(Clone)]
+pub struct SnakeEnv {
+    config: GameConfig,
+    options: EnvOptions,
+    state: SimState,
+    rng: GameRng,
+}
+
+impl SnakeEnv {
+    /// Environment for game of agent & bots of `config`
+    pub fn new(config: GameConfig, options: EnvOptions) -> Result<Self, ConfigError> {
+        let config = GameConfig {
+            players: 1,
+            ..config
+        };
+        config.validate()?;
+        let state = SimState::new(config.rules());
+        Ok(Self {
+            config,
+            options,
+            state,
+            rng: GameRng::new(0),
+        })
+    }
+
+    /// Start new game
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.state = SimState::new(self.config.rules());
+        self.rng = GameRng::new(seed);
+        self.observe()
+    }
+
+    /// Run one tick with agent action
+    ///
+    /// Returns observation after the tick, reward, whether episode is done,
+    /// and tick details. Done environment stays as is until reset.
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool, StepInfo) {
+        if self.is_done() {
+            return (self.observe(), 0., true, self.info(Vec::new()));
+        }
+
+        let mut inputs = vec![action.direction()];
+        for index in 1..self.state.snakes.len() {
+            let level = self.config.bot(index).unwrap_or_default();
+            inputs.push(bot::think(&self.state, index, level));
+        }
+        let events = self.state.step(&inputs, &mut self.rng);
+
+        let mut reward = self.options.tick_reward;
+        for event in &events {
+            match event {
+                SimEvent::MeatEaten { snake: AGENT, .. } => reward += self.options.meat_reward,
+                SimEvent::SnakeDied { snake: AGENT, .. } => reward += self.options.death_reward,
+                _ => {}
+            }
+        }
+        (self.observe(), reward, self.is_done(), self.info(events))
+    }
+
+    /// Board as seen by agent
+    pub fn observe(&self) -> Observation {
+        Observation::new(&self.state, AGENT)
+    }
+
+    /// Current game
+    pub fn state(&self) -> &SimState {
+        &self.state
+    }
+
+    /// Agent is dead, game is over or tick limit is hit
+    pub fn is_done(&self) -> bool {
+        !self.state.snakes[AGENT].alive || self.state.is_over() || self.is_truncated()
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.options.max_ticks > 0 && self.state.tick >= self.options.max_ticks
+    }
+
+    fn info(&self, events: Vec<SimEvent>) -> StepInfo {
+        let agent = &self.state.snakes[AGENT];
+        StepInfo {
+            tick: self.state.tick,
+            score: agent.stats.score,
+            length: agent.body.len(),
+            death: agent.death,
+            truncated: agent.alive && !self.state.is_over() && self.is_truncated(),
+            events,
+        }
+    }
+}
+
+/// Result of step in one of environments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    /// Observation after the tick, or of the new episode when done
+    pub observation: Observation,
+    pub reward: f32,
+    pub done: bool,
+    pub info: StepInfo,
+    /// Last observation of finished episode
+    pub terminal: Option<Observation>,
+}
+
+/// Batch of environments stepped in parallel
+///
+/// Environments are split between worker threads started once, calling thread
+/// steps the first part itself. Done environments start new episode right
+/// away, every new episode gets next unused seed.
+pub struct VecEnv {
+    /// Environments by part, `parts[i + 1]` is stepped by `workers[i]`
+    parts: Vec<Arc<Mutex<Vec<SnakeEnv>>>>,
+    workers: Vec<Worker>,
+    len: usize,
+    next_seed: u64,
+}
+
+/// Thread stepping one part of environments
+struct Worker {
+    /// Actions of the next step, worker stops when it is closed
+    actions: Option<Sender<Vec<Action>>>,
+    transitions: Receiver<Vec<Transition>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VecEnv {
+    pub fn new(config: GameConfig, options: EnvOptions, count: usize) -> Result<Self, ConfigError> {
+        let env = SnakeEnv::new(config, options)?;
+        let threads = thread::available_parallelism().map_or(1, usize::from);
+        let size = count.div_ceil(threads).max(1);
+        let mut envs = (0..count).map(|_| env.clone());
+        let parts: Vec<_> = (0..count.div_ceil(size))
+            .map(|_| Arc::new(Mutex::new(envs.by_ref().take(size).collect())))
+            .collect();
+        let workers = parts.iter().skip(1).map(Worker::spawn).collect();
+        Ok(Self {
+            parts,
+            workers,
+            len: count,
+            next_seed: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Current game of environment
+    pub fn state(&self, index: usize) -> SimState {
+        let mut left = index;
+        for part in &self.parts {
+            let envs = lock(part);
+            match envs.get(left) {
+                Some(env) => return env.state().clone(),
+                None => left -= envs.len(),
+            }
+        }
+        panic!("no environment {index}");
+    }
+
+    /// Start new games, environment `i` gets `seed + i`
+    pub fn reset(&mut self, seed: u64) -> Vec<Observation> {
+        self.next_seed = seed.wrapping_add(self.len as u64);
+        let mut index = 0;
+        let mut observations = Vec::with_capacity(self.len);
+        for part in &self.parts {
+            for env in lock(part).iter_mut() {
+                observations.push(env.reset(seed.wrapping_add(index)));
+                index += 1;
+            }
+        }
+        observations
+    }
+
+    /// Run one tick of every environment, `actions` are by environment
+    pub fn step(&mut self, actions: &[Action]) -> Vec<Transition> {
+        assert_eq!(actions.len(), self.len, "one action per environment");
+        let Some(first) = self.parts.first() else {
+            return Vec::new();
+        };
+        let first_len = lock(first).len();
+        for (worker, actions) in self
+            .workers
+            .iter()
+            .zip(actions[first_len..].chunks(first_len))
+        {
+            worker.send(actions.to_vec());
+        }
+        let mut transitions = step_part(&mut lock(first), &actions[..first_len]);
+        for worker in &self.workers {
+            transitions.extend(worker.recv());
+        }
+
+        let mut transitions_left = transitions.iter_mut();
+        for part in &self.parts {
+            for (env, transition) in lock(part).iter_mut().zip(&mut transitions_left) {
+                if transition.done {
+                    let observation = env.reset(self.next_seed);
+                    self.next_seed = self.next_seed.wrapping_add(1);
+                    transition.terminal =
+                        Some(std::mem::replace(&mut transition.observation, observation));
+                }
+            }
+        }
+        transitions
+    }
+}
+
+impl Drop for VecEnv {
+    fn drop(&mut self) {
+        for worker in &mut self.workers {
+            worker.actions.take();
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Worker {
+    fn spawn(part: &Arc<Mutex<Vec<SnakeEnv>>>) -> Self {
+        let part = part.clone();
+        let (actions, actions_receiver) = mpsc::channel::<Vec<Action>>();
+        let (transitions_sender, transitions) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            for actions in actions_receiver {
+                let transitions = step_part(&mut lock(&part), &actions);
+                if transitions_sender.send(transitions).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            actions: Some(actions),
+            transitions,
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&self, actions: Vec<Action>) {
+        if let Some(sender) = &self.actions {
+            sender.send(actions).expect("environment panicked");
+        }
+    }
+
+    fn recv(&self) -> Vec<Transition> {
+        self.transitions.recv().expect("environment panicked")
+    }
+}
+
+/// Step environments of one part
+fn step_part(envs: &mut [SnakeEnv], actions: &[Action]) -> Vec<Transition> {
+    envs.iter_mut()
+        .zip(actions)
+        .map(|(env, action)| {
+            let (observation, reward, done, info) = env.step(*action);
+            Transition {
+                observation,
+                reward,
+                done,
+                info,
+                terminal: None,
+            }
+        })
+        .collect()
+}
+
+fn lock(part: &Mutex<Vec<SnakeEnv>>) -> MutexGuard<'_, Vec<SnakeEnv>> {
+    part.lock().expect("environment panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::BotLevel;
+
+    fn config() -> GameConfig {
+        GameConfig {
+            map_width: 10,
+            map_height: 8,
+            bots: vec![BotLevel::Easy, BotLevel::Hard],
+            ..GameConfig::default()
+        }
+    }
+
+    #[test]
+    fn observation_covers_board_with_border() {
+        let mut env = SnakeEnv::new(config(), EnvOptions::default()).unwrap();
+        let observation = env.reset(1);
+        assert_eq!(observation.shape(), [Channel::COUNT, 10, 12]);
+        assert_eq!(observation.data.len(), Channel::COUNT * 10 * 12);
+        let head = env.state().snakes[AGENT].head();
+        let cell = |pos: IVec2| (pos.x as usize + 1, pos.y as usize + 1);
+        let (x, y) = cell(head);
+        assert_eq!(observation.get(Channel::Head, x, y), 1.);
+
+        let (observation, _, _, info) = env.step(Action::Keep);
+        assert_eq!(observation.shape(), [Channel::COUNT, 10, 12]);
+        assert_eq!(info.tick, 1);
+        let (x, y) = cell(env.state().snakes[AGENT].head());
+        assert_eq!(observation.get(Channel::Head, x, y), 1.);
+    }
+
+    #[test]
+    fn death_is_rewarded_and_ends_episode() {
+        let options = EnvOptions {
+            meat_reward: 0.,
+            death_reward: -5.,
+            tick_reward: 0.5,
+            max_ticks: 0,
+        };
+        let config = GameConfig {
+            border_mode: BorderMode::Solid,
+            bots: Vec::new(),
+            ..config()
+        };
+        let mut env = SnakeEnv::new(config, options).unwrap();
+        env.reset(1);
+        // Going straight ends at the wall
+        let (reward, info) = loop {
+            let (_, reward, done, info) = env.step(Action::Keep);
+            if done {
+                break (reward, info);
+            }
+            assert_eq!(reward, 0.5);
+        };
+        assert_eq!(reward, -4.5);
+        assert_eq!(info.death, Some(DeathCause::Wall));
+        assert!(!info.truncated);
+
+        let (_, reward, done, info) = env.step(Action::Keep);
+        assert_eq!((reward, done), (0., true));
+        assert!(info.events.is_empty());
+    }
+
+    #[test]
+    fn tick_limit_truncates_episode() {
+        let options = EnvOptions {
+            max_ticks: 3,
+            ..EnvOptions::default()
+        };
+        let mut env = SnakeEnv::new(config(), options).unwrap();
+        env.reset(1);
+        env.step(Action::Keep);
+        env.step(Action::Keep);
+        let (_, _, done, info) = env.step(Action::Keep);
+        assert!(done);
+        assert!(info.truncated);
+    }
+
+    #[test]
+    fn same_seed_plays_same_game() {
+        let play = |seed| {
+            let mut env = SnakeEnv::new(config(), EnvOptions::default()).unwrap();
+            env.reset(seed);
+            let actions = [Action::Up, Action::Left, Action::Down, Action::Keep];
+            for action in actions.iter().cycle().take(40) {
+                env.step(*action);
+            }
+            env.state().clone()
+        };
+        assert_eq!(play(5), play(5));
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let config = GameConfig {
+            bots: vec![BotLevel::Easy; 4],
+            ..config()
+        };
+        assert!(SnakeEnv::new(config.clone(), EnvOptions::default()).is_err());
+        assert!(VecEnv::new(config, EnvOptions::default(), 2).is_err());
+    }
+
+    #[test]
+    fn vec_env_matches_single_envs() {
+        let options = EnvOptions {
+            max_ticks: 20,
+            ..EnvOptions::default()
+        };
+        let mut envs = VecEnv::new(config(), options.clone(), 3).unwrap();
+        let mut singles: Vec<_> = (0..3)
+            .map(|_| SnakeEnv::new(config(), options.clone()).unwrap())
+            .collect();
+
+        let observations = envs.reset(10);
+        let mut next_seed = 13;
+        for (index, (env, observation)) in singles.iter_mut().zip(observations).enumerate() {
+            assert_eq!(env.reset(10 + index as u64), observation);
+        }
+
+        let actions = [Action::Keep, Action::Left, Action::Up];
+        let mut episodes = 0;
+        for _ in 0..50 {
+            let transitions = envs.step(&actions);
+            for ((env, action), transition) in singles.iter_mut().zip(actions).zip(&transitions) {
+                let (observation, reward, done, info) = env.step(action);
+                assert_eq!(transition.reward, reward);
+                assert_eq!(transition.done, done);
+                assert_eq!(transition.info, info);
+                if done {
+                    // New episode starts with next unused seed
+                    episodes += 1;
+                    assert_eq!(transition.terminal, Some(observation));
+                    assert_eq!(transition.observation, env.reset(next_seed));
+                    next_seed += 1;
+                } else {
+                    assert_eq!(transition.observation, observation);
+                    assert_eq!(transition.terminal, None);
+                }
+            }
+        }
+        assert!(episodes >= 6);
+        for (index, env) in singles.iter().enumerate() {
+            assert_eq!(&envs.state(index), env.state());
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Classic snake game on Bevy
+//!
+//! Add [`CameraPlugin`], [`GamePlugin`] and [`MenuPlugin`] to the app and
+//! insert [`GameState`] to get the whole game.
+
+use bevy::prelude::*;
+
+pub mod bot;
+mod camera;
+pub mod config;
+pub mod contest;
+mod game;
+pub mod gym;
+mod menu;
+pub mod net;
+pub mod replay;
+pub mod rollback;
+pub mod sim;
+pub mod tournament;
+
+pub use camera::CameraPlugin;
+pub use config::GameConfig;
+#[doc(hidden)]
+pub use game::replace_simulation;
+#[allow(deprecated)]
+pub use game::PlayerStats;
+pub use game::{
+    Autopilot, GamePlugin, GameResults, GameRng, GameSeed, MeatEaten, ReplayPlayer, ReplayRecorder,
+    Simulation, SnakeCatastrophicEvent, SnakeCollisionEvent, SnakeDeath, SnakeStats,
+};
+pub use menu::MenuPlugin;
+
+/// Global state of the game
+#[derive(Debug, Clone, Eq, PartialEq, Hash, States)]
+pub enum GameState {
+    MainMenu,
+    InGame,
+    Paused,
+    GameOver,
+    /// Snakes filled the whole board
+    Victory,
+}
@@ -1,42 +1,19 @@
 // Snake-rs
 // Mark Vodyanitskiy (@mvodya) 2024
 
-use bevy::prelude::*;
-
-mod camera;
-mod game;
-mod menu;
-
-const MAP_SIZE: Vec2 = Vec2::new(80., 50.);
-const SNAKE_FAT_STEPS: i32 = 5;
-
-#[derive(Debug, Clone, Eq, PartialEq, Hash, States)]
-pub enum GameState {
-    MainMenu,
-    InGame,
-    GameOver,
-}
+mod cli;
 
 fn main() {
-    App::new()
-        .insert_state(GameState::MainMenu)
-        .add_plugins((
-            DefaultPlugins.set(bevy::window::WindowPlugin {
-                primary_window: Some(bevy::window::Window {
-                    prevent_default_event_handling: true,
-                    canvas: Some("#snake-rs-canvas".into()),
-                    name: Some("snake-rs.app".into()),
-                    title: "snake-rs".into(),
-                    ..default()
-                }),
-                ..default()
-            }), // .set(bevy::log::LogPlugin {
-                //     level: bevy::log::Level::DEBUG,
-                //     ..default()
-                // })
-            camera::CameraPlugin,
-            game::GamePlugin,
-            menu::MenuPlugin,
-        ))
-        .run();
+    let mut args = std::env::args().skip(1).peekable();
+    let subcommand: fn(_) -> i32 = match args.peek().map(String::as_str) {
+        Some("verify") => cli::verify,
+        Some("server") => cli::server,
+        Some("contest") => cli::contest,
+        Some("tournament") => cli::tournament,
+        Some("bot") => cli::bot,
+        Some("loopback") => cli::loopback,
+        _ => return cli::play(args),
+    };
+    args.next();
+    std::process::exit(subcommand(args));
 }
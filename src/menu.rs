@@ -1,9 +1,14 @@
 // Snake-rs
 // Mark Vodyanitskiy (@mvodya) 2024
 
+use std::time::Duration;
+
 use bevy::prelude::*;
 
-use crate::{game::PlayerStats, GameState};
+use crate::{
+    game::{player_color, GameResults, GameRng},
+    GameState,
+};
 
 pub struct MenuPlugin;
 
@@ -19,9 +24,11 @@ impl Plugin for MenuPlugin {
             .add_systems(
                 Update,
                 (animate_game_over_text, anykey_check_game_over)
-                    .run_if(in_state(GameState::GameOver)),
+                    .run_if(in_state(GameState::GameOver).or_else(in_state(GameState::Victory))),
             )
-            .add_systems(OnExit(GameState::GameOver), hide_ui);
+            .add_systems(OnExit(GameState::GameOver), hide_ui)
+            .add_systems(OnEnter(GameState::Victory), show_victory)
+            .add_systems(OnExit(GameState::Victory), hide_ui);
     }
 }
 
@@ -125,13 +132,75 @@ fn anykey_check(
     }
 }
 
-fn show_game_over(mut commands: Commands, asset_server: Res<AssetServer>, stats: Res<PlayerStats>) {
+fn show_game_over(
+    commands: Commands,
+    asset_server: Res<AssetServer>,
+    results: Res<GameResults>,
+    rng: Res<GameRng>,
+) {
+    // Last alive snake wins game with several snakes
+    let (title, color) = match results.winner {
+        Some(winner) => (format!("P{} WINS", winner + 1), player_color(winner)),
+        None => ("GAME OVER".into(), Color::rgb(1., 0., 0.)),
+    };
+    let details = format!("seed: {}", rng.seed());
+    spawn_end_screen(commands, &asset_server, &title, color, &results, details);
+}
+
+fn show_victory(
+    commands: Commands,
+    asset_server: Res<AssetServer>,
+    results: Res<GameResults>,
+    rng: Res<GameRng>,
+) {
+    let details = format!(
+        "time: {}   //   seed: {}",
+        format_time(results.time),
+        rng.seed()
+    );
+    spawn_end_screen(
+        commands,
+        &asset_server,
+        "VICTORY",
+        Color::rgb(0., 1., 0.),
+        &results,
+        details,
+    );
+}
+
+/// Format duration as `m:ss`
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+const RESULTS_COLUMNS: [&str; 8] = [
+    "player", "score", "food", "distance", "length", "kills", "alive", "death",
+];
+
+/// Screen with title, results table, game details and restart hint
+fn spawn_end_screen(
+    mut commands: Commands,
+    asset_server: &AssetServer,
+    title: &str,
+    color: Color,
+    results: &GameResults,
+    details: String,
+) {
+    let text_style = |color| TextStyle {
+        font: asset_server.load("fonts/Minimal5x7.ttf"),
+        font_size: 20.,
+        color,
+    };
+
     commands
         .spawn((
             NodeBundle {
                 style: Style {
                     justify_self: JustifySelf::Center,
-                    top: Val::Vh(40.),
+                    top: Val::Vh(30.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
                     ..default()
                 },
                 ..default()
@@ -139,41 +208,61 @@ fn show_game_over(mut commands: Commands, asset_server: Res<AssetServer>, stats:
             UI,
         ))
         .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font: asset_server.load("fonts/Minimal5x7.ttf"),
+                    font_size: 120.,
+                    color,
+                },
+            ));
+
+            // Results table
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        display: Display::Grid,
+                        grid_template_columns: RepeatedGridTrack::auto(RESULTS_COLUMNS.len() as u16),
+                        column_gap: Val::Px(30.),
+                        row_gap: Val::Px(5.),
+                        margin: UiRect::vertical(Val::Px(30.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|table| {
+                    for column in RESULTS_COLUMNS {
+                        table.spawn(TextBundle::from_section(column, text_style(Color::GRAY)));
+                    }
+                    for (index, stats) in results.snakes.iter().enumerate() {
+                        let death = match (stats.death, stats.killer) {
+                            (Some(cause), Some(killer)) => format!("{cause} by P{}", killer + 1),
+                            (Some(cause), None) => cause.to_string(),
+                            (None, _) => "-".into(),
+                        };
+                        let row = [
+                            format!("P{}", index + 1),
+                            stats.score.to_string(),
+                            stats.food_eaten.to_string(),
+                            stats.distance_traveled.to_string(),
+                            stats.max_length.to_string(),
+                            stats.kills.to_string(),
+                            format_time(stats.time_alive),
+                            death,
+                        ];
+                        for cell in row {
+                            table.spawn(TextBundle::from_section(
+                                cell,
+                                text_style(player_color(index)),
+                            ));
+                        }
+                    }
+                });
+
             parent.spawn((
                 TextBundle::from_sections([
-                    TextSection::new(
-                        "GAME OVER",
-                        TextStyle {
-                            font: asset_server.load("fonts/Minimal5x7.ttf"),
-                            font_size: 120.,
-                            color: Color::Rgba {
-                                red: 1.0,
-                                green: 0.,
-                                blue: 0.,
-                                alpha: 1.,
-                            },
-                            ..default()
-                        },
-                    ),
-                    TextSection::new(
-                        format!(
-                            "\n\nscore: {}   //   food eaten: {}   //   distance traveled: {}",
-                            stats.score, stats.food_eaten, stats.distance_traveled
-                        ),
-                        TextStyle {
-                            font: asset_server.load("fonts/Minimal5x7.ttf"),
-                            font_size: 20.,
-                            ..default()
-                        },
-                    ),
-                    TextSection::new(
-                        "",
-                        TextStyle {
-                            font: asset_server.load("fonts/Minimal5x7.ttf"),
-                            font_size: 20.,
-                            ..default()
-                        },
-                    ),
+                    TextSection::new(details, text_style(Color::WHITE)),
+                    TextSection::new("", text_style(Color::WHITE)),
                 ])
                 .with_text_justify(JustifyText::Center),
                 GameOverText(0.),
@@ -198,13 +287,13 @@ fn animate_game_over_text(
 
         if timer.0 < 2. {
             let step_progress = ((timer.0 - 1.) * GAME_OVER_SUB_TEXT.len() as f64) as usize;
-            text.sections[2].value = GAME_OVER_SUB_TEXT[0..step_progress].into();
+            text.sections[1].value = GAME_OVER_SUB_TEXT[0..step_progress].into();
             // Fix glitch
             if timer.0 > 1.1 {
-                text.sections[2].value += "_";
+                text.sections[1].value += "_";
             }
         } else {
-            text.sections[2].value = GAME_OVER_SUB_TEXT.into();
+            text.sections[1].value = GAME_OVER_SUB_TEXT.into();
         }
     }
 }
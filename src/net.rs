@@ -0,0 +1,549 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Networked multiplayer
+//!
+//! With authoritative server, server runs the simulation and streams state
+//! of every tick to the clients, clients send only inputs of own snake.
+//! Messages are RON, one per line, over TCP.
+//!
+//! Peer-to-peer game of two players simulates the game on both peers with
+//! [`crate::rollback`], peers exchange only inputs as RON datagrams over UDP.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    bot,
+    rollback::PeerInputs,
+    sim::{SimEvent, SimRules, SimState, SnakeDirection},
+    GameConfig, GameRng,
+};
+
+/// Default port of the server
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// Message from client to server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Client is ready for new game
+    Ready,
+    /// Direction of client snake
+    Input(SnakeDirection),
+}
+
+/// Message from server to client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// Sent once after connection
+    Welcome { player: usize, config: GameConfig },
+    /// New game started
+    Start { seed: u64 },
+    /// State after game tick and events happened during it
+    Tick {
+        state: SimState,
+        events: Vec<SimEvent>,
+    },
+}
+
+/// Write message as one line
+fn send<T: Serialize>(mut stream: &TcpStream, message: &T) -> io::Result<()> {
+    let mut line =
+        ron::to_string(message).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+/// Read messages in separate thread
+///
+/// `None` is sent when connection is closed
+fn spawn_reader<T, F>(reader: impl BufRead + Send + 'static, mut handle: F)
+where
+    T: DeserializeOwned,
+    F: FnMut(Option<T>) -> bool + Send + 'static,
+{
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match ron::from_str(&line) {
+                Ok(message) => {
+                    if !handle(Some(message)) {
+                        return;
+                    }
+                }
+                Err(err) => warn!("Wrong message: {err}"),
+            }
+        }
+        handle(None);
+    });
+}
+
+/// Rules received from other side can not be played
+fn check_config(config: &GameConfig) -> io::Result<()> {
+    config
+        .validate()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Connection to the server, available as resource in client mode
+#[derive(Resource)]
+pub struct NetClient {
+    /// Index of own snake
+    pub player: usize,
+    stream: TcpStream,
+    receiver: Mutex<Receiver<Option<ServerMessage>>>,
+}
+
+impl NetClient {
+    /// Connect to server and wait for valid game config
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<(Self, GameConfig)> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let Ok(ServerMessage::Welcome { player, config }) = ron::from_str(&line) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "server did not send welcome",
+            ));
+        };
+        check_config(&config)?;
+
+        // Reader may hold messages sent right after welcome
+        let (sender, receiver) = mpsc::channel();
+        spawn_reader(reader, move |message| sender.send(message).is_ok());
+        let client = Self {
+            player,
+            stream,
+            receiver: Mutex::new(receiver),
+        };
+        Ok((client, config))
+    }
+
+    pub fn send(&self, message: &ClientMessage) -> io::Result<()> {
+        send(&self.stream, message)
+    }
+
+    /// Next received message, `Ok(None)` when server closed connection
+    pub fn try_recv(&self) -> Result<Option<ServerMessage>, TryRecvError> {
+        self.receiver
+            .lock()
+            .map_err(|_| TryRecvError::Disconnected)?
+            .try_recv()
+    }
+}
+
+impl Drop for NetClient {
+    /// Reader thread keeps a handle of the stream, so close connection itself
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Headless server
+///
+/// Waits for `config.players` clients, then runs games while all of them are
+/// connected. Every game starts when all clients are ready. Bots are played
+/// by the server.
+pub struct Server {
+    listener: TcpListener,
+    config: GameConfig,
+    seed: Option<u64>,
+}
+
+impl Server {
+    /// Listen for clients, `seed` is used for every game when set
+    pub fn bind(
+        addr: impl ToSocketAddrs,
+        config: GameConfig,
+        seed: Option<u64>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            config,
+            seed,
+        })
+    }
+
+    /// Address clients connect to
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Run games till some client leaves
+    pub fn run(self) -> io::Result<()> {
+        let Self {
+            listener,
+            config,
+            seed,
+        } = self;
+
+        // Accept players
+        let (sender, receiver) = mpsc::channel();
+        let mut clients = Vec::with_capacity(config.players);
+        for player in 0..config.players {
+            let (stream, address) = listener.accept()?;
+            stream.set_nodelay(true)?;
+            send(
+                &stream,
+                &ServerMessage::Welcome {
+                    player,
+                    config: config.clone(),
+                },
+            )?;
+            let sender = sender.clone();
+            spawn_reader(BufReader::new(stream.try_clone()?), move |message| {
+                sender.send((player, message)).is_ok()
+            });
+            clients.push(stream);
+            info!("Player {} joined from {}", player + 1, address);
+        }
+        let left = |player: usize| {
+            io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                format!("player {} left", player + 1),
+            )
+        };
+
+        let period = Duration::from_secs_f64(config.tick_seconds);
+        loop {
+            // Wait for all players
+            let mut ready = vec![false; clients.len()];
+            while !ready.iter().all(|ready| *ready) {
+                match receiver.recv() {
+                    Ok((player, Some(ClientMessage::Ready))) => ready[player] = true,
+                    Ok((_, Some(ClientMessage::Input(_)))) => {}
+                    Ok((player, None)) => return Err(left(player)),
+                    Err(_) => return Err(io::ErrorKind::BrokenPipe.into()),
+                }
+            }
+
+            let mut rng = GameRng::new(seed.unwrap_or_else(rand::random));
+            // Bots play till the end after every player is dead
+            let mut state = SimState::new(SimRules {
+                players: 0,
+                ..config.rules()
+            });
+            let mut inputs = vec![VecDeque::new(); clients.len()];
+            for stream in &clients {
+                send(stream, &ServerMessage::Start { seed: rng.seed() })?;
+            }
+            info!("Game started with seed {}", rng.seed());
+
+            let mut next_tick = Instant::now() + period;
+            while !state.is_over() {
+                // Collect inputs till next tick
+                while let Some(timeout) = next_tick.checked_duration_since(Instant::now()) {
+                    match receiver.recv_timeout(timeout) {
+                        Ok((player, Some(ClientMessage::Input(direction)))) => {
+                            // Same buffer size as local input
+                            let buffer: &mut VecDeque<_> = &mut inputs[player];
+                            if buffer.len() < 2 {
+                                buffer.push_back(direction);
+                            }
+                        }
+                        Ok((_, Some(ClientMessage::Ready))) => {}
+                        Ok((player, None)) => return Err(left(player)),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return Err(io::ErrorKind::BrokenPipe.into())
+                        }
+                    }
+                }
+                next_tick += period;
+
+                let mut tick_inputs: Vec<_> = inputs.iter_mut().map(VecDeque::pop_front).collect();
+                for (index, level) in config.bots.iter().enumerate() {
+                    tick_inputs.push(bot::think(&state, clients.len() + index, *level));
+                }
+                let events = state.step(&tick_inputs, &mut rng);
+                let message = ServerMessage::Tick {
+                    state: state.clone(),
+                    events,
+                };
+                for stream in &clients {
+                    send(stream, &message)?;
+                }
+            }
+
+            for (index, stats) in state.stats().iter().enumerate() {
+                info!(
+                    "Player {}: score: {}, kills: {}",
+                    index + 1,
+                    stats.score,
+                    stats.kills
+                );
+            }
+        }
+    }
+}
+
+/// Message between peers of peer-to-peer game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PeerMessage {
+    /// Joining peer looks for host
+    Hello,
+    /// Host accepted joining peer
+    Welcome { config: GameConfig, seed: u64 },
+    /// Inputs of game with given number
+    Inputs { game: u32, inputs: PeerInputs },
+}
+
+/// Max size of peer message
+const MAX_DATAGRAM: usize = 65_507;
+
+/// Connection to other peer, available as resource in peer-to-peer mode
+#[derive(Resource)]
+pub struct NetPeer {
+    /// Index of own snake, host plays the first one
+    pub player: usize,
+    /// Seed of the first game
+    seed: u64,
+    socket: UdpSocket,
+    /// Answer of host on repeated hello
+    welcome: Option<PeerMessage>,
+}
+
+impl NetPeer {
+    /// Wait for other peer, host picks rules of the game
+    pub fn host(addr: impl ToSocketAddrs, config: GameConfig, seed: u64) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        loop {
+            let (len, from) = socket.recv_from(&mut buffer)?;
+            if let Some(PeerMessage::Hello) = decode(&buffer[..len]) {
+                socket.connect(from)?;
+                info!("Peer joined from {from}");
+                break;
+            }
+        }
+        socket.set_nonblocking(true)?;
+
+        let peer = Self {
+            player: 0,
+            seed,
+            socket,
+            welcome: Some(PeerMessage::Welcome { config, seed }),
+        };
+        if let Some(welcome) = &peer.welcome {
+            peer.send(welcome)?;
+        }
+        Ok(peer)
+    }
+
+    /// Join hosting peer, returns valid rules of the game
+    pub fn join(addr: impl ToSocketAddrs) -> io::Result<(Self, GameConfig)> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+
+        // Say hello till host answers
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        let (config, seed) = loop {
+            socket.send(&encode(&PeerMessage::Hello)?)?;
+            match socket.recv(&mut buffer) {
+                Ok(len) => {
+                    if let Some(PeerMessage::Welcome { config, seed }) = decode(&buffer[..len]) {
+                        break (config, seed);
+                    }
+                }
+                Err(err) if is_retry(&err) => {}
+                Err(err) => return Err(err),
+            }
+        };
+        check_config(&config)?;
+        socket.set_read_timeout(None)?;
+        socket.set_nonblocking(true)?;
+
+        let peer = Self {
+            player: 1,
+            seed,
+            socket,
+            welcome: None,
+        };
+        Ok((peer, config))
+    }
+
+    /// Seed of game with given number
+    pub fn game_seed(&self, game: u32) -> u64 {
+        self.seed.wrapping_add(game as u64)
+    }
+
+    pub fn send(&self, message: &PeerMessage) -> io::Result<()> {
+        self.socket.send(&encode(message)?).map(|_| ())
+    }
+
+    /// Next received message, `Ok(None)` when there is nothing to read
+    pub fn try_recv(&self) -> io::Result<Option<PeerMessage>> {
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        loop {
+            let len = match self.socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(err) if is_retry(&err) => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            match decode(&buffer[..len]) {
+                // Welcome was lost, send it again
+                Some(PeerMessage::Hello) => {
+                    if let Some(welcome) = &self.welcome {
+                        self.send(welcome)?;
+                    }
+                }
+                Some(message) => return Ok(Some(message)),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Nothing to read now or other peer is not listening yet
+fn is_retry(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::ConnectionRefused
+    )
+}
+
+fn encode(message: &PeerMessage) -> io::Result<Vec<u8>> {
+    ron::to_string(message)
+        .map(String::into_bytes)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn decode(datagram: &[u8]) -> Option<PeerMessage> {
+    let message = std::str::from_utf8(datagram).ok()?;
+    match ron::from_str(message) {
+        Ok(message) => Some(message),
+        Err(err) => {
+            warn!("Wrong message: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::BotLevel;
+
+    /// Wait for next message from server
+    fn recv(client: &NetClient) -> Option<ServerMessage> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            match client.try_recv() {
+                Ok(message) => return message,
+                Err(TryRecvError::Empty) => thread::sleep(Duration::from_millis(1)),
+                Err(TryRecvError::Disconnected) => return None,
+            }
+        }
+        panic!("no message from server");
+    }
+
+    #[test]
+    fn client_plays_on_loopback_server() {
+        let config = GameConfig {
+            tick_seconds: 0.01,
+            bots: vec![BotLevel::Easy],
+            ..GameConfig::default()
+        };
+        let server = Server::bind("127.0.0.1:0", config.clone(), Some(7)).unwrap();
+        let addr = server.local_addr().unwrap();
+        let server = thread::spawn(move || server.run());
+
+        let (client, received) = NetClient::connect(addr).unwrap();
+        assert_eq!(client.player, 0);
+        assert_eq!(received, config);
+        client.send(&ClientMessage::Ready).unwrap();
+        assert!(matches!(
+            recv(&client),
+            Some(ServerMessage::Start { seed: 7 })
+        ));
+        client
+            .send(&ClientMessage::Input(SnakeDirection::Up))
+            .unwrap();
+        for tick in 1..=5 {
+            let Some(ServerMessage::Tick { state, .. }) = recv(&client) else {
+                panic!("server did not send tick {tick}");
+            };
+            assert_eq!(state.tick, tick);
+            assert_eq!(state.snakes.len(), 2);
+        }
+
+        // Server stops when player leaves
+        drop(client);
+        assert!(server.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn client_keeps_messages_sent_with_welcome() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let welcome = ServerMessage::Welcome {
+                player: 0,
+                config: GameConfig::default(),
+            };
+            // Both messages come in one segment
+            let mut lines = String::new();
+            for message in [welcome, ServerMessage::Start { seed: 3 }] {
+                lines.push_str(&ron::to_string(&message).unwrap());
+                lines.push('\n');
+            }
+            (&stream).write_all(lines.as_bytes()).unwrap();
+            stream
+        });
+
+        let (client, _) = NetClient::connect(addr).unwrap();
+        assert!(matches!(
+            recv(&client),
+            Some(ServerMessage::Start { seed: 3 })
+        ));
+        drop(server.join().unwrap());
+        assert!(recv(&client).is_none());
+    }
+
+    #[test]
+    fn client_rejects_check_config() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let config = GameConfig {
+                tick_seconds: f64::NAN,
+                ..GameConfig::default()
+            };
+            send(&stream, &ServerMessage::Welcome { player: 0, config }).unwrap();
+            stream
+        });
+
+        let err = NetClient::connect(addr).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        drop(server.join().unwrap());
+    }
+}
@@ -0,0 +1,299 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Replay file format
+//!
+//! Replay contains seed of the game and inputs for every tick, so the game
+//! can be simulated again exactly. Stored as RON.
+
+use std::{fmt, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ConfigError,
+    sim::{SimRules, SimState, SimStats, SnakeDirection},
+    GameConfig, GameRng,
+};
+
+/// Version of replay format
+pub const REPLAY_VERSION: u32 = 5;
+
+/// Inputs of one game tick
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayTick {
+    /// Directions picked from input buffers of every snake
+    pub directions: Vec<Option<SnakeDirection>>,
+    /// Speed up was requested
+    pub speed_up: bool,
+}
+
+/// Recorded game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    /// Rules of recorded game
+    #[serde(default)]
+    pub config: GameConfig,
+    pub ticks: Vec<ReplayTick>,
+    /// Final statistics of every snake, written after game over
+    #[serde(default)]
+    pub stats: Option<Vec<SimStats>>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, config: GameConfig) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            seed,
+            config,
+            ticks: Vec::new(),
+            stats: None,
+        }
+    }
+
+    /// Rules of recorded game which differ from `expected` rules
+    ///
+    /// Count of players & bots may differ, every other rule changes the score
+    pub fn rule_changes(&self, expected: &GameConfig) -> Vec<String> {
+        let rules = self.config.rules();
+        let expected = expected.rules();
+        let size = |rules: &SimRules| format!("{}x{}", rules.map_size.x, rules.map_size.y);
+        [
+            ("map size", size(&rules), size(&expected)),
+            (
+                "border mode",
+                rules.border_mode.to_string(),
+                expected.border_mode.to_string(),
+            ),
+            (
+                "meat score",
+                rules.meat_score.to_string(),
+                expected.meat_score.to_string(),
+            ),
+            (
+                "meat limit",
+                rules.meat_limit.to_string(),
+                expected.meat_limit.to_string(),
+            ),
+            (
+                "meat spawn interval",
+                rules.meat_spawn_interval.to_string(),
+                expected.meat_spawn_interval.to_string(),
+            ),
+            (
+                "starvation ticks",
+                rules.starvation_ticks.to_string(),
+                expected.starvation_ticks.to_string(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, value, expected)| value != expected)
+        .map(|(name, value, expected)| format!("{name}: {value} (expected {expected})"))
+        .collect()
+    }
+
+    /// Simulate recorded game again without rendering
+    pub fn simulate(&self) -> SimState {
+        let mut rng = GameRng::new(self.seed);
+        let mut state = SimState::new(self.config.rules());
+        for tick in &self.ticks {
+            if state.is_over() {
+                break;
+            }
+            state.step(&tick.directions, &mut rng);
+        }
+        state
+    }
+
+    /// Read replay with playable rules from RON string
+    pub fn from_ron(data: &str) -> Result<Self, ReplayError> {
+        let replay: Replay = ron::from_str(data).map_err(ReplayError::Parse)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(replay.version));
+        }
+        replay.config.validate().map_err(ReplayError::Config)?;
+        Ok(replay)
+    }
+
+    /// Write replay to RON string
+    pub fn to_ron(&self) -> Result<String, ReplayError> {
+        ron::to_string(self).map_err(ReplayError::Serialize)
+    }
+
+    /// Read replay from file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_ron(&fs::read_to_string(path).map_err(ReplayError::Io)?)
+    }
+
+    /// Write replay to file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        fs::write(path, self.to_ron()?).map_err(ReplayError::Io)
+    }
+}
+
+/// Failed to read or write replay
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    /// Replay was written by unsupported format version
+    Version(u32),
+    /// Rules of recorded game can not be played
+    Config(ConfigError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "replay io error: {err}"),
+            ReplayError::Parse(err) => write!(f, "replay parse error: {err}"),
+            ReplayError::Serialize(err) => write!(f, "replay serialize error: {err}"),
+            ReplayError::Version(version) => write!(
+                f,
+                "unsupported replay version {version} (expected {REPLAY_VERSION})"
+            ),
+            ReplayError::Config(err) => write!(f, "replay rules: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::{self, BotLevel},
+        sim::BorderMode,
+    };
+
+    fn config() -> GameConfig {
+        GameConfig {
+            map_width: 16,
+            map_height: 12,
+            players: 2,
+            bots: vec![BotLevel::Hard],
+            ..GameConfig::default()
+        }
+    }
+
+    /// Game of bots recorded like the game records players, with final state
+    fn record(seed: u64) -> (Replay, SimState) {
+        let mut replay = Replay::new(seed, config());
+        let mut rng = GameRng::new(seed);
+        let mut state = SimState::new(replay.config.rules());
+        let levels = [BotLevel::Easy, BotLevel::Normal, BotLevel::Hard];
+        while !state.is_over() && state.tick < 2_000 {
+            let directions: Vec<_> = levels
+                .iter()
+                .enumerate()
+                .map(|(snake, level)| bot::think(&state, snake, *level))
+                .collect();
+            state.step(&directions, &mut rng);
+            replay.ticks.push(ReplayTick {
+                directions,
+                speed_up: false,
+            });
+        }
+        replay.stats = Some(state.stats());
+        (replay, state)
+    }
+
+    #[test]
+    fn saved_replay_loads_same() {
+        let (replay, _) = record(3);
+        let path = std::env::temp_dir().join(format!("snake-rs-replay-{}.ron", std::process::id()));
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), replay);
+    }
+
+    #[test]
+    fn simulated_replay_ends_like_recorded_game() {
+        let (replay, state) = record(11);
+        assert!(state.tick > 0);
+        let simulated = replay.simulate();
+        assert_eq!(simulated, state);
+        assert_eq!(Some(simulated.stats()), replay.stats);
+    }
+
+    #[test]
+    fn other_version_is_rejected() {
+        for version in [REPLAY_VERSION - 1, REPLAY_VERSION + 1] {
+            let replay = Replay {
+                version,
+                ..Replay::new(1, config())
+            };
+            let result = Replay::from_ron(&replay.to_ron().unwrap());
+            assert!(matches!(result, Err(ReplayError::Version(v)) if v == version));
+        }
+    }
+
+    #[test]
+    fn unplayable_rules_are_rejected() {
+        let replay = Replay::new(
+            1,
+            GameConfig {
+                map_width: 2,
+                ..config()
+            },
+        );
+        let result = Replay::from_ron(&replay.to_ron().unwrap());
+        assert!(matches!(result, Err(ReplayError::Config(_))));
+    }
+
+    #[test]
+    fn rule_changes_ignore_players_and_bots() {
+        let replay = Replay::new(1, config());
+        let expected = GameConfig {
+            players: 1,
+            bots: vec![BotLevel::Easy, BotLevel::Easy, BotLevel::Normal],
+            ..config()
+        };
+        assert!(replay.rule_changes(&expected).is_empty());
+        assert!(replay.rule_changes(&config()).is_empty());
+    }
+
+    #[test]
+    fn rule_changes_report_scoring_rules() {
+        let replay = Replay::new(1, config());
+        let changed = [
+            GameConfig {
+                map_width: 20,
+                ..config()
+            },
+            GameConfig {
+                map_height: 20,
+                ..config()
+            },
+            GameConfig {
+                border_mode: BorderMode::Solid,
+                ..config()
+            },
+            GameConfig {
+                meat_score: 10,
+                ..config()
+            },
+            GameConfig {
+                meat_limit: 1,
+                ..config()
+            },
+            GameConfig {
+                meat_spawn_seconds: 6.,
+                ..config()
+            },
+            GameConfig {
+                starvation_seconds: 10.,
+                ..config()
+            },
+        ];
+        for expected in changed {
+            let changes = replay.rule_changes(&expected);
+            assert_eq!(changes.len(), 1, "{expected:?}: {changes:?}");
+        }
+    }
+}
@@ -0,0 +1,336 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Rollback netcode for peer-to-peer games
+//!
+//! Every peer simulates the whole game itself and exchanges only inputs.
+//! Missing inputs of remote players are predicted (snake keeps direction),
+//! when real inputs arrive the game is restored from snapshot and simulated
+//! again up to the current tick.
+
+use std::collections::VecDeque;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    replay::{Replay, ReplayTick},
+    sim::{SimEvent, SimState, SnakeDirection},
+    GameConfig, GameRng,
+};
+
+/// Max ticks simulated ahead of confirmed inputs, peer waits after that
+pub const MAX_PREDICTION: u64 = 8;
+
+/// Inputs of one player for other peers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerInputs {
+    pub player: usize,
+    /// Tick of the first input
+    pub start: u64,
+    pub inputs: Vec<Option<SnakeDirection>>,
+    /// Count of known inputs of every player, acknowledges received inputs
+    pub received: Vec<u64>,
+}
+
+/// Game state before tick
+#[derive(Clone)]
+struct Snapshot {
+    state: SimState,
+    rng: GameRng,
+}
+
+/// Game of one peer
+pub struct RollbackSession {
+    /// Index of local snake
+    local: usize,
+    /// Received inputs of every player by tick
+    inputs: Vec<Vec<Option<SnakeDirection>>>,
+    /// Count of local inputs received by every player
+    acked: Vec<u64>,
+    /// Snapshots before every tick from `base` till current tick
+    snapshots: VecDeque<Snapshot>,
+    base: u64,
+    /// Game state with predicted inputs
+    state: SimState,
+    rng: GameRng,
+    /// First tick simulated with wrong prediction
+    mispredicted: Option<u64>,
+    rollbacks: u32,
+}
+
+impl RollbackSession {
+    pub fn new(state: SimState, rng: GameRng, local: usize) -> Self {
+        let players = state.snakes.len();
+        Self {
+            local,
+            inputs: vec![Vec::new(); players],
+            acked: vec![0; players],
+            snapshots: VecDeque::new(),
+            base: state.tick,
+            state,
+            rng,
+            mispredicted: None,
+            rollbacks: 0,
+        }
+    }
+
+    /// Game state with predicted inputs of remote players
+    pub fn state(&self) -> &SimState {
+        &self.state
+    }
+
+    /// Game state after last tick with inputs of every player
+    pub fn confirmed_state(&self) -> &SimState {
+        self.snapshots
+            .get((self.confirmed() - self.base) as usize)
+            .map_or(&self.state, |snapshot| &snapshot.state)
+    }
+
+    /// Count of ticks with inputs of every player
+    pub fn confirmed(&self) -> u64 {
+        let known = self.inputs.iter().map(|inputs| inputs.len() as u64).min();
+        known.unwrap_or(0).min(self.state.tick)
+    }
+
+    /// Count of restores from snapshot
+    pub fn rollbacks(&self) -> u32 {
+        self.rollbacks
+    }
+
+    /// Inputs of every player for confirmed ticks
+    pub fn confirmed_inputs(&self) -> Vec<Vec<Option<SnakeDirection>>> {
+        (0..self.confirmed() as usize)
+            .map(|tick| self.inputs.iter().map(|inputs| inputs[tick]).collect())
+            .collect()
+    }
+
+    /// Save inputs received from other peer
+    pub fn add_inputs(&mut self, message: &PeerInputs) {
+        // Other peer can not have more local inputs than were sent
+        let sent = self.inputs[self.local].len() as u64;
+        if let Some(acked) = self.acked.get_mut(message.player) {
+            let received = message.received.get(self.local).copied().unwrap_or(0);
+            *acked = (*acked).max(received.min(sent));
+        }
+        if message.player == self.local {
+            return;
+        }
+        let Some(inputs) = self.inputs.get_mut(message.player) else {
+            return;
+        };
+        // Inputs after lost message are sent again, skip them till then
+        let known = inputs.len() as u64;
+        if message.start > known {
+            return;
+        }
+        let new = message.inputs.iter().skip((known - message.start) as usize);
+        for (tick, input) in (known..).zip(new) {
+            // Only turns differ from prediction
+            if tick < self.state.tick && input.is_some() {
+                self.mispredicted = Some(self.mispredicted.map_or(tick, |first| first.min(tick)));
+            }
+            inputs.push(*input);
+        }
+    }
+
+    /// Local inputs not received by every player yet
+    pub fn message(&self) -> PeerInputs {
+        let start = (0..self.acked.len())
+            .filter(|player| *player != self.local)
+            .map(|player| self.acked[player])
+            .min()
+            .unwrap_or(self.state.tick);
+        PeerInputs {
+            player: self.local,
+            start,
+            inputs: self.inputs[self.local][start as usize..].to_vec(),
+            received: self
+                .inputs
+                .iter()
+                .map(|inputs| inputs.len() as u64)
+                .collect(),
+        }
+    }
+
+    /// Restore game before mispredicted tick and simulate it again
+    ///
+    /// Returns true when state was changed
+    pub fn rollback(&mut self) -> bool {
+        let Some(tick) = self.mispredicted.take() else {
+            return false;
+        };
+        let current = self.state.tick;
+        let index = (tick - self.base) as usize;
+        let snapshot = self.snapshots[index].clone();
+        self.snapshots.truncate(index);
+        self.state = snapshot.state;
+        self.rng = snapshot.rng;
+        while self.state.tick < current {
+            self.simulate();
+        }
+        self.rollbacks += 1;
+        self.forget_confirmed();
+        true
+    }
+
+    /// Game may run next tick
+    ///
+    /// Waits for inputs of slow peers and stops when game is over
+    pub fn can_advance(&self) -> bool {
+        !self.state.is_over() && self.state.tick - self.confirmed() < MAX_PREDICTION
+    }
+
+    /// Run next tick with local input
+    pub fn advance(&mut self, input: Option<SnakeDirection>) -> Vec<SimEvent> {
+        self.inputs[self.local].push(input);
+        let events = self.simulate();
+        self.forget_confirmed();
+        events
+    }
+
+    /// Run tick with known or predicted inputs
+    fn simulate(&mut self) -> Vec<SimEvent> {
+        self.snapshots.push_back(Snapshot {
+            state: self.state.clone(),
+            rng: self.rng.clone(),
+        });
+        let tick = self.state.tick as usize;
+        let inputs: Vec<_> = self
+            .inputs
+            .iter()
+            .map(|inputs| inputs.get(tick).copied().flatten())
+            .collect();
+        self.state.step(&inputs, &mut self.rng)
+    }
+
+    /// Drop snapshots which are never restored
+    fn forget_confirmed(&mut self) {
+        while self.base < self.confirmed() {
+            self.snapshots.pop_front();
+            self.base += 1;
+        }
+    }
+}
+
+/// Result of loopback game
+pub struct LoopbackReport {
+    /// Confirmed ticks of both peers
+    pub ticks: u64,
+    /// Rollbacks of both peers
+    pub rollbacks: [u32; 2],
+    /// Peers ended with the same game
+    pub agree: bool,
+    /// Game simulated again from confirmed inputs ended the same way
+    pub replay_agrees: bool,
+}
+
+/// Play game of two peers with random inputs in one process
+///
+/// Inputs reach other peer after `delay` ticks, `loss` part of them is lost.
+/// Game stops after game over or `max_ticks` confirmed ticks.
+pub fn loopback(
+    config: &GameConfig,
+    seed: u64,
+    delay: u64,
+    loss: f64,
+    max_ticks: u64,
+) -> LoopbackReport {
+    let config = GameConfig {
+        players: 2,
+        bots: Vec::new(),
+        ..config.clone()
+    };
+    let state = SimState::new(config.rules());
+    let mut peers =
+        [0, 1].map(|player| RollbackSession::new(state.clone(), GameRng::new(seed), player));
+    let mut network = GameRng::new(seed ^ u64::MAX);
+    let mut players = [1, 2].map(|player| GameRng::new(seed.wrapping_add(player)));
+    // Messages in flight with time of arrival
+    let mut in_flight: VecDeque<(u64, usize, PeerInputs)> = VecDeque::new();
+
+    let mut time = 0;
+    let finished =
+        |peer: &RollbackSession| peer.confirmed_state().is_over() || peer.confirmed() >= max_ticks;
+    while !peers.iter().all(finished) {
+        while in_flight.front().is_some_and(|(at, ..)| *at <= time) {
+            let (_, to, message) = in_flight.pop_front().unwrap();
+            peers[to].add_inputs(&message);
+        }
+        for (index, peer) in peers.iter_mut().enumerate() {
+            peer.rollback();
+            if peer.can_advance() && peer.state().tick < max_ticks {
+                peer.advance(random_turn(&mut players[index]));
+            }
+            if !network.gen_bool(loss) {
+                in_flight.push_back((time + delay, 1 - index, peer.message()));
+            }
+        }
+        time += 1;
+    }
+
+    let ticks = peers[0].confirmed();
+    let mut replay = Replay::new(seed, config);
+    replay.ticks = peers[0]
+        .confirmed_inputs()
+        .into_iter()
+        .map(|directions| ReplayTick {
+            directions,
+            speed_up: false,
+        })
+        .collect();
+    LoopbackReport {
+        ticks,
+        rollbacks: peers.each_ref().map(RollbackSession::rollbacks),
+        agree: peers[0].confirmed_state() == peers[1].confirmed_state(),
+        replay_agrees: &replay.simulate() == peers[0].confirmed_state(),
+    }
+}
+
+/// Turn to random side sometimes
+fn random_turn(rng: &mut impl Rng) -> Option<SnakeDirection> {
+    const TURNS: [SnakeDirection; 4] = [
+        SnakeDirection::Up,
+        SnakeDirection::Down,
+        SnakeDirection::Left,
+        SnakeDirection::Right,
+    ];
+    rng.gen_bool(0.2)
+        .then(|| TURNS[rng.gen_range(0..TURNS.len())])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_with_delay_and_loss_agrees() {
+        for seed in 0..4 {
+            let report = loopback(&GameConfig::default(), seed, 5, 0.2, 1_000);
+            assert!(report.agree, "peers disagree, seed {seed}");
+            assert!(report.replay_agrees, "replay disagrees, seed {seed}");
+            // Delayed inputs were predicted wrong sometimes
+            assert!(report.rollbacks.iter().all(|rollbacks| *rollbacks > 0));
+        }
+    }
+
+    #[test]
+    fn acknowledge_of_unsent_inputs_is_ignored() {
+        let config = GameConfig {
+            players: 2,
+            ..GameConfig::default()
+        };
+        let state = SimState::new(config.rules());
+        let mut session = RollbackSession::new(state, GameRng::new(0), 0);
+        session.advance(None);
+        session.add_inputs(&PeerInputs {
+            player: 1,
+            start: 0,
+            inputs: vec![None],
+            received: vec![u64::MAX, 1],
+        });
+        let message = session.message();
+        assert_eq!(message.start, 1);
+        assert!(message.inputs.is_empty());
+    }
+}
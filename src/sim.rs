@@ -0,0 +1,1120 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Headless simulation of the snake rules
+//!
+//! Knows nothing about sprites, windows or Bevy schedules. The game plugins
+//! only feed inputs into [`SimState::step`] and render the result.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    fmt,
+    str::FromStr,
+};
+
+use bevy::math::IVec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Rules of the simulation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimRules {
+    /// Size of the board in cells
+    pub map_size: IVec2,
+    /// Ticks between meat spawns
+    pub meat_spawn_interval: u64,
+    /// Maximum count of meat on the board
+    pub meat_limit: usize,
+    /// Score for one eaten meat
+    pub meat_score: u32,
+    /// What happens on map edges
+    pub border_mode: BorderMode,
+    /// Count of snakes on the board
+    pub snakes: usize,
+    /// Ticks without meat before snake starves, 0 disables starvation
+    pub starvation_ticks: u64,
+    /// Snakes of players come first, game is over when all of them are dead,
+    /// 0 plays till one snake is left
+    #[serde(default)]
+    pub players: usize,
+}
+
+impl Default for SimRules {
+    fn default() -> Self {
+        crate::GameConfig::default().rules()
+    }
+}
+
+impl fmt::Display for SimRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "map: {}x{} ({}), snakes: {} ({} players), meat: score {}, limit {}, every {} ticks, starvation: ",
+            self.map_size.x,
+            self.map_size.y,
+            self.border_mode,
+            self.snakes,
+            self.players,
+            self.meat_score,
+            self.meat_limit,
+            self.meat_spawn_interval
+        )?;
+        match self.starvation_ticks {
+            0 => write!(f, "off"),
+            ticks => write!(f, "{ticks} ticks"),
+        }
+    }
+}
+
+/// Behaviour of map edges
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BorderMode {
+    /// Snake leaves the map and appears on the other side
+    #[default]
+    Wrap,
+    /// Snake dies on the map edge
+    Solid,
+}
+
+impl fmt::Display for BorderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BorderMode::Wrap => write!(f, "wrap"),
+            BorderMode::Solid => write!(f, "solid"),
+        }
+    }
+}
+
+impl FromStr for BorderMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "wrap" => Ok(BorderMode::Wrap),
+            "solid" => Ok(BorderMode::Solid),
+            _ => Err(format!("unknown border mode {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SnakeDirection {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl SnakeDirection {
+    pub fn get_vector(&self) -> IVec2 {
+        match self {
+            SnakeDirection::Up => IVec2::new(0, 1),
+            SnakeDirection::Right => IVec2::new(1, 0),
+            SnakeDirection::Down => IVec2::new(0, -1),
+            SnakeDirection::Left => IVec2::new(-1, 0),
+        }
+    }
+
+    pub fn is_oposite(&self, other: &SnakeDirection) -> bool {
+        self.get_vector().dot(other.get_vector()) == -1
+    }
+}
+
+impl fmt::Display for SnakeDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnakeDirection::Up => write!(f, "up"),
+            SnakeDirection::Right => write!(f, "right"),
+            SnakeDirection::Down => write!(f, "down"),
+            SnakeDirection::Left => write!(f, "left"),
+        }
+    }
+}
+
+impl FromStr for SnakeDirection {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "up" => Ok(SnakeDirection::Up),
+            "right" => Ok(SnakeDirection::Right),
+            "down" => Ok(SnakeDirection::Down),
+            "left" => Ok(SnakeDirection::Left),
+            _ => Err(format!("unknown direction {value}")),
+        }
+    }
+}
+
+/// Snake on the board
+///
+/// HEAD = body[0], TAIL = body[len - 1]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimSnake {
+    pub direction: SnakeDirection,
+    pub body: VecDeque<IVec2>,
+    pub alive: bool,
+    pub stats: SimStats,
+    /// Ticks since last eaten meat
+    pub hunger: u64,
+    /// Why snake died, `None` while alive
+    pub death: Option<DeathCause>,
+    /// Index of snake credited for the kill
+    pub killer: Option<usize>,
+}
+
+impl SimSnake {
+    pub fn head(&self) -> IVec2 {
+        self.body[0]
+    }
+}
+
+/// Meat on the board
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimMeat {
+    pub id: u32,
+    pub position: IVec2,
+    /// Hue of meat color in degrees
+    pub hue: f32,
+}
+
+/// Statistics of the snake
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimStats {
+    pub score: u32,
+    pub food_eaten: u32,
+    pub distance_traveled: u32,
+    /// Snakes killed by this snake
+    #[serde(default)]
+    pub kills: u32,
+}
+
+/// Something that occupies a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SimOccupant {
+    /// Snake element, `segment` 0 is the head
+    Snake {
+        snake: usize,
+        segment: usize,
+    },
+    Meat {
+        id: u32,
+    },
+    /// Solid map edge, `side` points outside of the map
+    Wall {
+        side: IVec2,
+    },
+}
+
+/// Why snake died
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeathCause {
+    /// Hit own body
+    SelfBody,
+    /// Hit body of other snake
+    OtherBody,
+    /// Crashed into head of other snake
+    HeadOn,
+    /// Hit solid map edge
+    Wall,
+    /// Did not eat for too long
+    Starvation,
+    /// Removed from the game for breaking rules
+    Disqualified,
+}
+
+impl fmt::Display for DeathCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeathCause::SelfBody => write!(f, "self"),
+            DeathCause::OtherBody => write!(f, "other snake"),
+            DeathCause::HeadOn => write!(f, "head-on"),
+            DeathCause::Wall => write!(f, "wall"),
+            DeathCause::Starvation => write!(f, "starvation"),
+            DeathCause::Disqualified => write!(f, "disqualified"),
+        }
+    }
+}
+
+/// Things happened during one tick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SimEvent {
+    /// Snake head entered an occupied cell
+    SnakeCollision {
+        snake: usize,
+        other: SimOccupant,
+        position: IVec2,
+    },
+    /// Snake died, `killer` is credited for the kill
+    SnakeDied {
+        snake: usize,
+        position: IVec2,
+        cause: DeathCause,
+        killer: Option<usize>,
+    },
+    /// Snake ate meat and grown by one element
+    MeatEaten {
+        snake: usize,
+        meat: u32,
+        position: IVec2,
+        hue: f32,
+    },
+    /// New meat appeared on the board
+    MeatSpawned {
+        meat: u32,
+        position: IVec2,
+        hue: f32,
+    },
+    /// No free cell left on the board, alive snakes won
+    BoardFull,
+}
+
+/// Whole state of one game
+///
+/// Call [`SimState::rebuild_grid`] after changing `snakes`, `meats` or map size
+/// by hand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SimStateData")]
+pub struct SimState {
+    pub rules: SimRules,
+    pub tick: u64,
+    pub snakes: Vec<SimSnake>,
+    pub meats: Vec<SimMeat>,
+    /// No free cell left on the board
+    pub board_full: bool,
+    next_meat_id: u32,
+    #[serde(skip)]
+    grid: OccupancyGrid,
+}
+
+impl PartialEq for SimState {
+    /// Grid is derived from snakes & meats, so it is not compared
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            rules,
+            tick,
+            snakes,
+            meats,
+            board_full,
+            next_meat_id,
+            grid: _,
+        } = self;
+        *rules == other.rules
+            && *tick == other.tick
+            && *snakes == other.snakes
+            && *meats == other.meats
+            && *board_full == other.board_full
+            && *next_meat_id == other.next_meat_id
+    }
+}
+
+/// Serialized part of [`SimState`], grid is built again after loading
+#[derive(Deserialize)]
+struct SimStateData {
+    rules: SimRules,
+    tick: u64,
+    snakes: Vec<SimSnake>,
+    meats: Vec<SimMeat>,
+    board_full: bool,
+    next_meat_id: u32,
+}
+
+impl From<SimStateData> for SimState {
+    fn from(data: SimStateData) -> Self {
+        let mut state = Self {
+            rules: data.rules,
+            tick: data.tick,
+            snakes: data.snakes,
+            meats: data.meats,
+            board_full: data.board_full,
+            next_meat_id: data.next_meat_id,
+            grid: OccupancyGrid::default(),
+        };
+        state.rebuild_grid();
+        state
+    }
+}
+
+/// Alive snakes & meat by board cell
+///
+/// Updated by every tick together with snakes and meats, so collisions and
+/// free cells are found without looking through all snakes.
+#[derive(Debug, Clone, Default)]
+struct OccupancyGrid {
+    size: IVec2,
+    /// Count of snake elements in every cell, more than one on collision
+    snakes: Vec<u16>,
+    meat: Vec<bool>,
+}
+
+impl OccupancyGrid {
+    fn new(size: IVec2) -> Self {
+        let cells = (size.x * size.y) as usize;
+        Self {
+            size,
+            snakes: vec![0; cells],
+            meat: vec![false; cells],
+        }
+    }
+
+    /// Index of cell, `None` outside of the board
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        let inside = pos.x >= 0 && pos.y >= 0 && pos.x < self.size.x && pos.y < self.size.y;
+        inside.then(|| (pos.y * self.size.x + pos.x) as usize)
+    }
+
+    fn snakes_at(&self, pos: IVec2) -> u16 {
+        self.index(pos).map_or(0, |index| self.snakes[index])
+    }
+
+    fn has_meat(&self, pos: IVec2) -> bool {
+        self.index(pos).is_some_and(|index| self.meat[index])
+    }
+
+    fn add_snake(&mut self, pos: IVec2) {
+        if let Some(index) = self.index(pos) {
+            self.snakes[index] += 1;
+        }
+    }
+
+    fn remove_snake(&mut self, pos: IVec2) {
+        if let Some(index) = self.index(pos) {
+            self.snakes[index] -= 1;
+        }
+    }
+
+    fn set_meat(&mut self, pos: IVec2, meat: bool) {
+        if let Some(index) = self.index(pos) {
+            self.meat[index] = meat;
+        }
+    }
+
+    /// Free cells in order of rows
+    fn free_cells(&self) -> impl Iterator<Item = IVec2> + '_ {
+        let width = self.size.x.max(1);
+        self.snakes
+            .iter()
+            .zip(&self.meat)
+            .enumerate()
+            .filter(|(_, (snakes, meat))| **snakes == 0 && !**meat)
+            .map(move |(index, _)| IVec2::new(index as i32 % width, index as i32 / width))
+    }
+}
+
+impl SimState {
+    /// Creates board with snakes placed in rows across the center
+    pub fn new(rules: SimRules) -> Self {
+        let count = rules.snakes.max(1) as i32;
+        let snakes = (0..count)
+            .map(|index| SimSnake {
+                direction: SnakeDirection::Right,
+                body: VecDeque::from([IVec2::new(
+                    rules.map_size.x / 2,
+                    rules.map_size.y * (index + 1) / (count + 1),
+                )]),
+                alive: true,
+                stats: SimStats::default(),
+                hunger: 0,
+                death: None,
+                killer: None,
+            })
+            .collect();
+        let mut state = Self {
+            rules,
+            tick: 0,
+            snakes,
+            meats: Vec::new(),
+            board_full: false,
+            next_meat_id: 0,
+            grid: OccupancyGrid::default(),
+        };
+        state.rebuild_grid();
+        state
+    }
+
+    /// Fill occupancy grid again
+    ///
+    /// Every tick keeps the grid in sync, call it after changing `snakes` or
+    /// `meats` by hand.
+    pub fn rebuild_grid(&mut self) {
+        let mut grid = OccupancyGrid::new(self.rules.map_size);
+        for snake in self.snakes.iter().filter(|snake| snake.alive) {
+            for pos in &snake.body {
+                grid.add_snake(*pos);
+            }
+        }
+        for meat in &self.meats {
+            grid.set_meat(meat.position, true);
+        }
+        self.grid = grid;
+    }
+
+    /// Cell has element of alive snake
+    pub fn has_snake(&self, pos: IVec2) -> bool {
+        self.grid.snakes_at(pos) > 0
+    }
+
+    /// Cell has meat
+    pub fn has_meat(&self, pos: IVec2) -> bool {
+        self.grid.has_meat(pos)
+    }
+
+    /// Game is over when board is full or no snake is alive
+    ///
+    /// With several snakes game ends when only one snake is left, or when
+    /// every snake of players is dead
+    pub fn is_over(&self) -> bool {
+        let alive = self.snakes.iter().filter(|snake| snake.alive).count();
+        let players_dead = self.rules.players > 0
+            && self
+                .snakes
+                .iter()
+                .take(self.rules.players)
+                .all(|snake| !snake.alive);
+        self.board_full || alive == 0 || (self.snakes.len() > 1 && alive == 1) || players_dead
+    }
+
+    /// Statistics of every snake
+    pub fn stats(&self) -> Vec<SimStats> {
+        self.snakes.iter().map(|snake| snake.stats).collect()
+    }
+
+    /// Cells occupied by alive snakes and meat
+    ///
+    /// Earlier snake element is kept when snakes overlap
+    pub fn occupancy(&self) -> HashMap<IVec2, SimOccupant> {
+        let mut occupancy = HashMap::new();
+        for meat in &self.meats {
+            occupancy.insert(meat.position, SimOccupant::Meat { id: meat.id });
+        }
+        for (snake, sim_snake) in self.snakes.iter().enumerate().rev() {
+            if !sim_snake.alive {
+                continue;
+            }
+            for (segment, pos) in sim_snake.body.iter().enumerate().rev() {
+                occupancy.insert(*pos, SimOccupant::Snake { snake, segment });
+            }
+        }
+        occupancy
+    }
+
+    /// Cell next to `pos` in `direction`, `None` behind solid wall
+    pub fn neighbour(&self, pos: IVec2, direction: SnakeDirection) -> Option<IVec2> {
+        let next = pos + direction.get_vector();
+        match self.rules.border_mode {
+            BorderMode::Wrap => Some(wrap(next, self.rules.map_size)),
+            BorderMode::Solid => {
+                (outside(next, self.rules.map_size) == IVec2::ZERO).then_some(next)
+            }
+        }
+    }
+
+    /// Pick random cell without snakes and meat
+    pub fn random_free_cell(&self, rng: &mut impl Rng) -> Option<IVec2> {
+        let free = self.grid.free_cells().count();
+        if free == 0 {
+            return None;
+        }
+        let index = rng.gen_range(0..free);
+        self.grid.free_cells().nth(index)
+    }
+
+    /// Remove alive snake from the game before the next tick
+    pub fn disqualify(&mut self, index: usize) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        if self.snakes.get(index).is_some_and(|snake| snake.alive) {
+            self.kill(index, DeathCause::Disqualified, None, &mut events);
+        }
+        events
+    }
+
+    /// Mark snake as dead and credit the killer
+    fn kill(
+        &mut self,
+        index: usize,
+        cause: DeathCause,
+        killer: Option<usize>,
+        events: &mut Vec<SimEvent>,
+    ) {
+        let snake = &mut self.snakes[index];
+        for pos in &snake.body {
+            self.grid.remove_snake(*pos);
+        }
+        snake.alive = false;
+        snake.death = Some(cause);
+        snake.killer = killer;
+        events.push(SimEvent::SnakeDied {
+            snake: index,
+            position: snake.head(),
+            cause,
+            killer,
+        });
+        if let Some(killer) = killer {
+            self.snakes[killer].stats.kills += 1;
+        }
+    }
+
+    /// Run one game tick
+    ///
+    /// `inputs` contains requested direction for every snake (by index)
+    pub fn step(&mut self, inputs: &[Option<SnakeDirection>], rng: &mut impl Rng) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        self.tick += 1;
+        debug_assert_eq!(
+            self.grid.size, self.rules.map_size,
+            "grid is stale, call rebuild_grid after changing the state"
+        );
+
+        // Apply inputs
+        for (snake, input) in self.snakes.iter_mut().zip(inputs) {
+            if let Some(direction) = input {
+                if !snake.direction.is_oposite(direction) {
+                    snake.direction = *direction;
+                }
+            }
+        }
+
+        // Move snakes, keep vacated tails for growth
+        let heads: Vec<IVec2> = self.snakes.iter().map(SimSnake::head).collect();
+        let mut dead = Vec::new();
+        let mut tails = Vec::with_capacity(self.snakes.len());
+        for (index, snake) in self.snakes.iter_mut().enumerate() {
+            if !snake.alive {
+                tails.push(None);
+                continue;
+            }
+            let next = snake.head() + snake.direction.get_vector();
+            let next = match self.rules.border_mode {
+                BorderMode::Wrap => wrap(next, self.rules.map_size),
+                BorderMode::Solid => {
+                    let side = outside(next, self.rules.map_size);
+                    if side != IVec2::ZERO {
+                        // Snake stays in front of the wall
+                        events.push(SimEvent::SnakeCollision {
+                            snake: index,
+                            other: SimOccupant::Wall { side },
+                            position: next,
+                        });
+                        dead.push((index, DeathCause::Wall, None));
+                        tails.push(None);
+                        continue;
+                    }
+                    next
+                }
+            };
+            snake.body.push_front(next);
+            self.grid.add_snake(next);
+            let tail = snake.body.pop_back();
+            if let Some(tail) = tail {
+                self.grid.remove_snake(tail);
+            }
+            tails.push(tail);
+            snake.stats.distance_traveled += 1;
+            snake.hunger += 1;
+        }
+
+        // Check collisions of snake heads
+        let crashed: Vec<usize> = dead.iter().map(|(index, ..)| *index).collect();
+        for (index, snake) in self.snakes.iter().enumerate() {
+            if !snake.alive || crashed.contains(&index) {
+                continue;
+            }
+            let head = snake.head();
+            // Heads swapped cells, snake of one element leaves nothing to hit
+            let swapped = self.snakes.iter().enumerate().find(|(other_index, other)| {
+                *other_index != index
+                    && other.alive
+                    && other.body.len() == 1
+                    && !crashed.contains(other_index)
+                    && heads[*other_index] == head
+                    && other.head() == heads[index]
+            });
+            if let Some((other_index, other)) = swapped {
+                events.push(SimEvent::SnakeCollision {
+                    snake: index,
+                    other: SimOccupant::Snake {
+                        snake: other_index,
+                        segment: 0,
+                    },
+                    position: head,
+                });
+                match snake.body.len().cmp(&other.body.len()) {
+                    Ordering::Greater => {}
+                    Ordering::Less => dead.push((index, DeathCause::HeadOn, Some(other_index))),
+                    Ordering::Equal => dead.push((index, DeathCause::HeadOn, None)),
+                }
+            }
+            // Only own head is in the cell
+            if self.grid.snakes_at(head) < 2 {
+                continue;
+            }
+            for (other_index, other) in self.snakes.iter().enumerate() {
+                if !other.alive {
+                    continue;
+                }
+                for (segment, pos) in other.body.iter().enumerate() {
+                    // Do not react on self & react only on same position
+                    if *pos != head || (other_index == index && segment == 0) {
+                        continue;
+                    }
+                    events.push(SimEvent::SnakeCollision {
+                        snake: index,
+                        other: SimOccupant::Snake {
+                            snake: other_index,
+                            segment,
+                        },
+                        position: head,
+                    });
+                    if dead.iter().any(|(dead, ..)| *dead == index) {
+                        continue;
+                    }
+                    // Heads met in one cell or passed through each other
+                    let head_on = other_index != index
+                        && !crashed.contains(&other_index)
+                        && (segment == 0 || (segment == 1 && heads[index] == other.head()));
+                    let (cause, killer) = if other_index == index {
+                        (DeathCause::SelfBody, None)
+                    } else if !head_on {
+                        (DeathCause::OtherBody, Some(other_index))
+                    } else {
+                        // Longer snake survives head-on crash, equal snakes both die
+                        match snake.body.len().cmp(&other.body.len()) {
+                            Ordering::Greater => continue,
+                            Ordering::Less => (DeathCause::HeadOn, Some(other_index)),
+                            Ordering::Equal => (DeathCause::HeadOn, None),
+                        }
+                    };
+                    dead.push((index, cause, killer));
+                }
+            }
+        }
+        for (index, cause, killer) in dead {
+            self.kill(index, cause, killer, &mut events);
+        }
+
+        // Eat meat
+        for (index, snake) in self.snakes.iter_mut().enumerate() {
+            if !snake.alive {
+                continue;
+            }
+            let head = snake.head();
+            if !self.grid.has_meat(head) {
+                continue;
+            }
+            let Some(found) = self.meats.iter().position(|meat| meat.position == head) else {
+                continue;
+            };
+            let meat = self.meats.swap_remove(found);
+            self.grid.set_meat(head, false);
+            events.push(SimEvent::SnakeCollision {
+                snake: index,
+                other: SimOccupant::Meat { id: meat.id },
+                position: head,
+            });
+            events.push(SimEvent::MeatEaten {
+                snake: index,
+                meat: meat.id,
+                position: head,
+                hue: meat.hue,
+            });
+            // Grow into vacated cell
+            if let Some(tail) = tails[index] {
+                snake.body.push_back(tail);
+                self.grid.add_snake(tail);
+            }
+            snake.stats.score += self.rules.meat_score;
+            snake.stats.food_eaten += 1;
+            snake.hunger = 0;
+        }
+
+        // Starve snakes without meat
+        if self.rules.starvation_ticks > 0 {
+            let starved: Vec<usize> = (0..self.snakes.len())
+                .filter(|index| {
+                    let snake = &self.snakes[*index];
+                    snake.alive && snake.hunger >= self.rules.starvation_ticks
+                })
+                .collect();
+            for index in starved {
+                self.kill(index, DeathCause::Starvation, None, &mut events);
+            }
+        }
+
+        // Spawn meat
+        if self.tick.is_multiple_of(self.rules.meat_spawn_interval)
+            && self.meats.len() < self.rules.meat_limit
+        {
+            if let Some(position) = self.random_free_cell(rng) {
+                let hue = rng.gen_range(0. ..=360.);
+                let meat = SimMeat {
+                    id: self.next_meat_id,
+                    position,
+                    hue,
+                };
+                self.next_meat_id += 1;
+                self.grid.set_meat(position, true);
+                events.push(SimEvent::MeatSpawned {
+                    meat: meat.id,
+                    position,
+                    hue,
+                });
+                self.meats.push(meat);
+            }
+        }
+
+        // Alive snakes & meat never share cells, so just count them
+        let alive = self.snakes.iter().filter(|snake| snake.alive);
+        let occupied =
+            alive.clone().map(|snake| snake.body.len()).sum::<usize>() + self.meats.len();
+        let cells = (self.rules.map_size.x * self.rules.map_size.y) as usize;
+        if !self.board_full && alive.count() > 0 && occupied >= cells {
+            self.board_full = true;
+            events.push(SimEvent::BoardFull);
+        }
+
+        events
+    }
+}
+
+/// Portal on borders of world
+fn wrap(pos: IVec2, map_size: IVec2) -> IVec2 {
+    IVec2::new(pos.x.rem_euclid(map_size.x), pos.y.rem_euclid(map_size.y))
+}
+
+/// Side of the map crossed by position, zero when position is inside
+fn outside(pos: IVec2, map_size: IVec2) -> IVec2 {
+    let axis = |value: i32, size: i32| {
+        if value < 0 {
+            -1
+        } else if value >= size {
+            1
+        } else {
+            0
+        }
+    };
+    IVec2::new(axis(pos.x, map_size.x), axis(pos.y, map_size.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GameRng;
+
+    use SnakeDirection::*;
+
+    fn snake(body: &[(i32, i32)], direction: SnakeDirection) -> SimSnake {
+        SimSnake {
+            direction,
+            body: body.iter().map(|&(x, y)| IVec2::new(x, y)).collect(),
+            alive: true,
+            stats: SimStats::default(),
+            hunger: 0,
+            death: None,
+            killer: None,
+        }
+    }
+
+    /// Board without meat spawn and starvation
+    fn board(
+        size: (i32, i32),
+        border_mode: BorderMode,
+        snakes: Vec<SimSnake>,
+        meats: &[(i32, i32)],
+    ) -> SimState {
+        let mut state = SimState::new(SimRules {
+            map_size: IVec2::new(size.0, size.1),
+            meat_spawn_interval: u64::MAX,
+            border_mode,
+            snakes: snakes.len(),
+            starvation_ticks: 0,
+            ..SimRules::default()
+        });
+        state.snakes = snakes;
+        state.meats = meats
+            .iter()
+            .zip(0..)
+            .map(|(&(x, y), id)| SimMeat {
+                id,
+                position: IVec2::new(x, y),
+                hue: 0.,
+            })
+            .collect();
+        state.next_meat_id = meats.len() as u32;
+        state.rebuild_grid();
+        state
+    }
+
+    fn step(state: &mut SimState, inputs: &[Option<SnakeDirection>]) -> Vec<SimEvent> {
+        state.step(inputs, &mut GameRng::new(0))
+    }
+
+    fn cells(snake: &SimSnake) -> Vec<(i32, i32)> {
+        snake.body.iter().map(|pos| (pos.x, pos.y)).collect()
+    }
+
+    #[test]
+    fn wrap_moves_through_edges() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![snake(&[(9, 5)], Right), snake(&[(3, 0)], Down)],
+            &[],
+        );
+        step(&mut state, &[None, None]);
+        assert_eq!(cells(&state.snakes[0]), [(0, 5)]);
+        assert_eq!(cells(&state.snakes[1]), [(3, 9)]);
+        assert!(state.snakes.iter().all(|snake| snake.alive));
+    }
+
+    #[test]
+    fn solid_edge_kills() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Solid,
+            vec![snake(&[(9, 5)], Right)],
+            &[],
+        );
+        let events = step(&mut state, &[None]);
+        let snake = &state.snakes[0];
+        assert!(!snake.alive);
+        assert_eq!(snake.death, Some(DeathCause::Wall));
+        // Snake stays in front of the wall
+        assert_eq!(cells(snake), [(9, 5)]);
+        assert!(events.contains(&SimEvent::SnakeCollision {
+            snake: 0,
+            other: SimOccupant::Wall { side: IVec2::X },
+            position: IVec2::new(10, 5),
+        }));
+    }
+
+    #[test]
+    fn solid_moves_inside() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Solid,
+            vec![snake(&[(5, 5)], Right)],
+            &[],
+        );
+        step(&mut state, &[Some(Up)]);
+        assert_eq!(cells(&state.snakes[0]), [(5, 6)]);
+        // Opposite direction is ignored
+        step(&mut state, &[Some(Down)]);
+        assert_eq!(cells(&state.snakes[0]), [(5, 7)]);
+        assert_eq!(state.snakes[0].stats.distance_traveled, 2);
+    }
+
+    #[test]
+    fn eating_grows_into_vacated_tail() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![snake(&[(2, 5), (1, 5)], Right)],
+            &[(3, 5)],
+        );
+        let events = step(&mut state, &[None]);
+        let snake = &state.snakes[0];
+        assert_eq!(cells(snake), [(3, 5), (2, 5), (1, 5)]);
+        assert_eq!(snake.stats.food_eaten, 1);
+        assert_eq!(snake.stats.score, state.rules.meat_score);
+        assert!(state.meats.is_empty());
+        assert!(!state.has_meat(IVec2::new(3, 5)));
+        assert!(state.has_snake(IVec2::new(1, 5)));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SimEvent::MeatEaten {
+                snake: 0,
+                meat: 0,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn chasing_own_tail_is_allowed() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![snake(&[(1, 1), (1, 2), (2, 2), (2, 1)], Down)],
+            &[],
+        );
+        step(&mut state, &[Some(Right)]);
+        let snake = &state.snakes[0];
+        assert!(snake.alive);
+        assert_eq!(cells(snake), [(2, 1), (1, 1), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn own_body_kills() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![snake(&[(1, 1), (1, 2), (2, 2), (2, 1), (2, 0)], Down)],
+            &[],
+        );
+        step(&mut state, &[Some(Right)]);
+        let snake = &state.snakes[0];
+        assert!(!snake.alive);
+        assert_eq!(snake.death, Some(DeathCause::SelfBody));
+        assert_eq!(snake.killer, None);
+    }
+
+    #[test]
+    fn other_body_kills_and_credits_owner() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![
+                snake(&[(1, 1)], Right),
+                snake(&[(2, 3), (2, 2), (2, 1), (2, 0)], Up),
+            ],
+            &[],
+        );
+        step(&mut state, &[None, None]);
+        assert!(!state.snakes[0].alive);
+        assert_eq!(state.snakes[0].death, Some(DeathCause::OtherBody));
+        assert_eq!(state.snakes[0].killer, Some(1));
+        assert!(state.snakes[1].alive);
+        assert_eq!(state.snakes[1].stats.kills, 1);
+        // Dead snake leaves the grid
+        assert!(!state.has_snake(IVec2::new(1, 1)));
+    }
+
+    #[test]
+    fn head_on_longer_snake_wins() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![
+                snake(&[(2, 5), (1, 5), (0, 5)], Right),
+                snake(&[(4, 5), (5, 5)], Left),
+            ],
+            &[],
+        );
+        step(&mut state, &[None, None]);
+        assert!(state.snakes[0].alive);
+        assert!(!state.snakes[1].alive);
+        assert_eq!(state.snakes[1].death, Some(DeathCause::HeadOn));
+        assert_eq!(state.snakes[1].killer, Some(0));
+        assert_eq!(state.snakes[0].stats.kills, 1);
+    }
+
+    #[test]
+    fn head_on_snakes_of_one_element_swapping_cells_both_die() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![snake(&[(2, 5)], Right), snake(&[(3, 5)], Left)],
+            &[],
+        );
+        let events = step(&mut state, &[None, None]);
+        for snake in &state.snakes {
+            assert!(!snake.alive);
+            assert_eq!(snake.death, Some(DeathCause::HeadOn));
+            assert_eq!(snake.killer, None);
+        }
+        assert!(events.contains(&SimEvent::SnakeCollision {
+            snake: 0,
+            other: SimOccupant::Snake {
+                snake: 1,
+                segment: 0,
+            },
+            position: IVec2::new(3, 5),
+        }));
+    }
+
+    #[test]
+    fn head_on_longer_snake_wins_over_one_element() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![snake(&[(2, 5), (1, 5)], Right), snake(&[(3, 5)], Left)],
+            &[],
+        );
+        step(&mut state, &[None, None]);
+        assert!(state.snakes[0].alive);
+        assert!(!state.snakes[1].alive);
+        assert_eq!(state.snakes[1].death, Some(DeathCause::HeadOn));
+        assert_eq!(state.snakes[1].killer, Some(0));
+    }
+
+    #[test]
+    fn head_on_equal_snakes_both_die() {
+        // Heads pass through each other
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![
+                snake(&[(2, 5), (1, 5)], Right),
+                snake(&[(3, 5), (4, 5)], Left),
+            ],
+            &[],
+        );
+        step(&mut state, &[None, None]);
+        for snake in &state.snakes {
+            assert!(!snake.alive);
+            assert_eq!(snake.death, Some(DeathCause::HeadOn));
+            assert_eq!(snake.killer, None);
+        }
+        assert!(state.is_over());
+    }
+
+    #[test]
+    fn meat_spawns_on_free_cells() {
+        for seed in 0..20 {
+            let mut state = board(
+                (3, 2),
+                BorderMode::Wrap,
+                vec![snake(&[(1, 0), (0, 0), (0, 1), (1, 1), (2, 1)], Right)],
+                &[],
+            );
+            state.rules.meat_spawn_interval = 1;
+            let events = state.step(&[None], &mut GameRng::new(seed));
+            // Snake moved to (2, 0), only (2, 1) is free
+            assert_eq!(state.meats.len(), 1);
+            assert_eq!(state.meats[0].position, IVec2::new(2, 1));
+            assert!(events.iter().any(|event| matches!(
+                event,
+                SimEvent::MeatSpawned { position, .. } if *position == IVec2::new(2, 1)
+            )));
+        }
+    }
+
+    #[test]
+    fn game_ends_with_players() {
+        let mut state = board(
+            (10, 10),
+            BorderMode::Wrap,
+            vec![
+                snake(&[(1, 1)], Right),
+                snake(&[(1, 3)], Right),
+                snake(&[(1, 5)], Right),
+            ],
+            &[],
+        );
+        state.rules.players = 1;
+        assert!(!state.is_over());
+        state.disqualify(0);
+        assert!(state.is_over());
+
+        // Bots play till the end without players
+        state.rules.players = 0;
+        assert!(!state.is_over());
+        state.disqualify(1);
+        assert!(state.is_over());
+    }
+
+    #[test]
+    fn filled_board_is_full() {
+        let mut state = board(
+            (3, 1),
+            BorderMode::Wrap,
+            vec![snake(&[(1, 0), (0, 0)], Right)],
+            &[(2, 0)],
+        );
+        let events = step(&mut state, &[None]);
+        assert!(events.contains(&SimEvent::BoardFull));
+        assert!(state.board_full);
+        assert!(state.is_over());
+        assert_eq!(state.random_free_cell(&mut GameRng::new(0)), None);
+    }
+}
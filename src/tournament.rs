@@ -0,0 +1,628 @@
+// Snake-rs
+// Mark Vodyanitskiy (@mvodya) 2024
+
+//! Tournaments between bots
+//!
+//! Entrants play many [`run_contest`] games on different seeds. Snakes of
+//! every game are ranked by survival, then by score, and ranks update Elo
+//! ratings of every pair of entrants in the game.
+
+use std::{
+    fmt, io,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    contest::{run_contest, ContestReport, ContestSnake, Contestant},
+    GameConfig,
+};
+
+/// Rating of entrant before the first game
+pub const START_ELO: f64 = 1500.;
+/// Max rating change of one game
+const ELO_K: f64 = 32.;
+
+/// How entrants are put into games
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Pairing {
+    /// Every group of entrants plays every seed
+    #[default]
+    RoundRobin,
+    /// Every round groups entrants with close points
+    Swiss,
+}
+
+impl fmt::Display for Pairing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Pairing::RoundRobin => "round-robin",
+            Pairing::Swiss => "swiss",
+        })
+    }
+}
+
+impl FromStr for Pairing {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "round-robin" => Ok(Pairing::RoundRobin),
+            "swiss" => Ok(Pairing::Swiss),
+            _ => Err(format!("unknown pairing {value}")),
+        }
+    }
+}
+
+/// Settings of tournament
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentOptions {
+    pub pairing: Pairing,
+    /// Seeds for every group in round robin, rounds in swiss
+    pub games: usize,
+    /// Snakes in one game
+    pub size: usize,
+    /// Seed of the first game, next games use next seeds
+    pub seed: u64,
+    pub move_timeout: Duration,
+    pub max_ticks: u64,
+    /// Games played at once
+    ///
+    /// Bots of parallel games share CPU, so move timeout of every game is
+    /// multiplied by `jobs`. Timings are fair only with 1.
+    pub jobs: usize,
+}
+
+impl Default for TournamentOptions {
+    fn default() -> Self {
+        Self {
+            pairing: Pairing::RoundRobin,
+            games: 10,
+            size: 2,
+            seed: 0,
+            move_timeout: Duration::from_millis(100),
+            max_ticks: 20_000,
+            jobs: 1,
+        }
+    }
+}
+
+/// Result of one snake in tournament game
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnakeRecord {
+    /// Index of entrant
+    pub entrant: usize,
+    pub name: String,
+    /// 1 is the best, equal snakes share place
+    pub place: usize,
+    pub score: u32,
+    pub food_eaten: u32,
+    pub kills: u32,
+    pub ticks_alive: u64,
+    pub alive: bool,
+    pub death: Option<String>,
+    pub disqualified: Option<String>,
+}
+
+/// Tournament game
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GameRecord {
+    pub round: usize,
+    pub seed: u64,
+    pub ticks: u64,
+    pub snakes: Vec<SnakeRecord>,
+}
+
+/// Totals of one entrant
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Standing {
+    pub name: String,
+    pub elo: f64,
+    pub games: u32,
+    /// Games finished first without ties
+    pub wins: u32,
+    /// Share of beaten opponents in every game, byes give 1
+    pub points: f64,
+    pub score: u64,
+    pub food_eaten: u64,
+    pub kills: u64,
+    pub ticks_alive: u64,
+    pub disqualifications: u32,
+}
+
+impl Standing {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            elo: START_ELO,
+            games: 0,
+            wins: 0,
+            points: 0.,
+            score: 0,
+            food_eaten: 0,
+            kills: 0,
+            ticks_alive: 0,
+            disqualifications: 0,
+        }
+    }
+
+    pub fn average_score(&self) -> f64 {
+        self.score as f64 / self.games.max(1) as f64
+    }
+
+    pub fn average_ticks_alive(&self) -> f64 {
+        self.ticks_alive as f64 / self.games.max(1) as f64
+    }
+}
+
+/// Result of tournament
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TournamentReport {
+    /// Entrants by index
+    pub standings: Vec<Standing>,
+    /// Games in the order ratings were updated
+    pub games: Vec<GameRecord>,
+}
+
+impl TournamentReport {
+    /// Standings from the best rating
+    pub fn ranking(&self) -> Vec<&Standing> {
+        let mut ranking: Vec<_> = self.standings.iter().collect();
+        ranking.sort_by(|a, b| b.elo.total_cmp(&a.elo));
+        ranking
+    }
+
+    /// Every snake of every game, one per line
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "round,seed,ticks,entrant,name,place,score,food_eaten,kills,ticks_alive,alive,death,disqualified\n",
+        );
+        for game in &self.games {
+            for snake in &game.snakes {
+                let fields = [
+                    game.round.to_string(),
+                    game.seed.to_string(),
+                    game.ticks.to_string(),
+                    snake.entrant.to_string(),
+                    csv_field(&snake.name),
+                    snake.place.to_string(),
+                    snake.score.to_string(),
+                    snake.food_eaten.to_string(),
+                    snake.kills.to_string(),
+                    snake.ticks_alive.to_string(),
+                    snake.alive.to_string(),
+                    csv_field(snake.death.as_deref().unwrap_or_default()),
+                    csv_field(snake.disqualified.as_deref().unwrap_or_default()),
+                ];
+                csv.push_str(&fields.join(","));
+                csv.push('\n');
+            }
+        }
+        csv
+    }
+
+    /// Standings & games
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is serializable")
+    }
+}
+
+/// Print standings table from the best rating
+pub fn print_ranking(report: &TournamentReport) {
+    println!(
+        "{:>4}  {:<24} {:>7} {:>6} {:>5} {:>7} {:>10} {:>6} {:>6} {:>10} {:>3}",
+        "rank",
+        "name",
+        "elo",
+        "games",
+        "wins",
+        "points",
+        "avg score",
+        "food",
+        "kills",
+        "avg ticks",
+        "dq"
+    );
+    for (rank, standing) in report.ranking().into_iter().enumerate() {
+        println!(
+            "{:>4}  {:<24} {:>7.1} {:>6} {:>5} {:>7.1} {:>10.1} {:>6} {:>6} {:>10.1} {:>3}",
+            rank + 1,
+            standing.name,
+            standing.elo,
+            standing.games,
+            standing.wins,
+            standing.points,
+            standing.average_score(),
+            standing.food_eaten,
+            standing.kills,
+            standing.average_ticks_alive(),
+            standing.disqualifications
+        );
+    }
+}
+
+/// Quote field with separators or quotes
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Play tournament of entrants
+pub fn run_tournament(
+    config: &GameConfig,
+    entrants: &[Contestant],
+    options: &TournamentOptions,
+) -> io::Result<TournamentReport> {
+    let mut report = TournamentReport {
+        standings: entrant_names(entrants)
+            .into_iter()
+            .map(Standing::new)
+            .collect(),
+        games: Vec::new(),
+    };
+    let size = options.size.clamp(1, entrants.len().max(1));
+
+    match options.pairing {
+        Pairing::RoundRobin => {
+            let games: Vec<_> = groups(entrants.len(), size)
+                .into_iter()
+                .flat_map(|group| {
+                    (0..options.games).map(move |game| {
+                        // Entrants change places on the board
+                        let mut group = group.clone();
+                        group.rotate_left(game % size);
+                        (0, options.seed.wrapping_add(game as u64), group)
+                    })
+                })
+                .collect();
+            play_games(config, entrants, options, games, &mut report)?;
+        }
+        Pairing::Swiss => {
+            let mut played = vec![vec![false; entrants.len()]; entrants.len()];
+            for round in 0..options.games {
+                let seed = options.seed.wrapping_add(round as u64);
+                let mut games = Vec::new();
+                for group in swiss_groups(&report.standings, &played, size) {
+                    if group.len() == 1 {
+                        report.standings[group[0]].points += 1.;
+                        continue;
+                    }
+                    for a in &group {
+                        for b in &group {
+                            played[*a][*b] = true;
+                        }
+                    }
+                    games.push((round, seed, group));
+                }
+                play_games(config, entrants, options, games, &mut report)?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Unique names of entrants
+fn entrant_names(entrants: &[Contestant]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for entrant in entrants {
+        let name = entrant.name();
+        let same = names
+            .iter()
+            .filter(|other| other.split(" #").next() == Some(name.as_str()))
+            .count();
+        names.push(if same == 0 {
+            name
+        } else {
+            format!("{name} #{}", same + 1)
+        });
+    }
+    names
+}
+
+/// Every combination of `size` entrants
+fn groups(entrants: usize, size: usize) -> Vec<Vec<usize>> {
+    let mut groups = Vec::new();
+    let mut group: Vec<usize> = (0..size).collect();
+    if size == 0 || size > entrants {
+        return groups;
+    }
+    loop {
+        groups.push(group.clone());
+        // Next combination in lexicographic order
+        let Some(position) = (0..size).rev().find(|i| group[*i] < entrants - size + i) else {
+            return groups;
+        };
+        group[position] += 1;
+        for i in position + 1..size {
+            group[i] = group[i - 1] + 1;
+        }
+    }
+}
+
+/// Groups of entrants with close points, pairs avoid rematches when possible
+///
+/// Last entrant gets a bye (group of one) when entrants do not split evenly.
+fn swiss_groups(standings: &[Standing], played: &[Vec<bool>], size: usize) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..standings.len()).collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (&standings[*a], &standings[*b]);
+        b.points.total_cmp(&a.points).then(b.elo.total_cmp(&a.elo))
+    });
+
+    let mut groups = Vec::new();
+    while !order.is_empty() {
+        let mut group = vec![order.remove(0)];
+        while group.len() < size && !order.is_empty() {
+            let next = if size == 2 {
+                order
+                    .iter()
+                    .position(|other| !played[group[0]][*other])
+                    .unwrap_or(0)
+            } else {
+                0
+            };
+            group.push(order.remove(next));
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// Play games in `options.jobs` threads, then record them in order
+fn play_games(
+    config: &GameConfig,
+    entrants: &[Contestant],
+    options: &TournamentOptions,
+    games: Vec<(usize, u64, Vec<usize>)>,
+    report: &mut TournamentReport,
+) -> io::Result<()> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(games.len()));
+    let threads = options.jobs.clamp(1, games.len().max(1));
+    let move_timeout = options.move_timeout * threads as u32;
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some((_, seed, group)) = games.get(index) else {
+                    break;
+                };
+                let contestants: Vec<_> = group.iter().map(|i| entrants[*i].clone()).collect();
+                let result =
+                    run_contest(config, &contestants, *seed, move_timeout, options.max_ticks);
+                results.lock().unwrap().push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    for ((round, _, group), (_, result)) in games.into_iter().zip(results) {
+        record_game(report, round, &group, result?);
+    }
+    Ok(())
+}
+
+/// Rank snakes of the game and update standings
+fn record_game(
+    report: &mut TournamentReport,
+    round: usize,
+    group: &[usize],
+    contest: ContestReport,
+) {
+    let key = |snake: &ContestSnake| (snake.alive, snake.ticks_alive, snake.stats.score);
+    let places: Vec<usize> = contest
+        .snakes
+        .iter()
+        .map(|snake| {
+            1 + contest
+                .snakes
+                .iter()
+                .filter(|other| key(other) > key(snake))
+                .count()
+        })
+        .collect();
+
+    // Elo & points from every pair of snakes
+    let opponents = (group.len() - 1).max(1) as f64;
+    let deltas: Vec<(f64, f64)> = (0..group.len())
+        .map(|a| {
+            let mut delta = 0.;
+            let mut points = 0.;
+            for b in (0..group.len()).filter(|b| *b != a) {
+                let actual = match places[a].cmp(&places[b]) {
+                    std::cmp::Ordering::Less => 1.,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.,
+                };
+                let (rating, other) = (
+                    report.standings[group[a]].elo,
+                    report.standings[group[b]].elo,
+                );
+                let expected = 1. / (1. + 10f64.powf((other - rating) / 400.));
+                delta += ELO_K / opponents * (actual - expected);
+                points += actual / opponents;
+            }
+            (delta, points)
+        })
+        .collect();
+
+    let mut snakes = Vec::with_capacity(group.len());
+    for (((entrant, snake), place), (delta, points)) in
+        group.iter().zip(contest.snakes).zip(&places).zip(deltas)
+    {
+        let standing = &mut report.standings[*entrant];
+        standing.elo += delta;
+        standing.points += points;
+        standing.games += 1;
+        if *place == 1 && places.iter().filter(|place| **place == 1).count() == 1 {
+            standing.wins += 1;
+        }
+        standing.score += u64::from(snake.stats.score);
+        standing.food_eaten += u64::from(snake.stats.food_eaten);
+        standing.kills += u64::from(snake.stats.kills);
+        standing.ticks_alive += snake.ticks_alive;
+        if snake.disqualified.is_some() {
+            standing.disqualifications += 1;
+        }
+        snakes.push(SnakeRecord {
+            entrant: *entrant,
+            name: standing.name.clone(),
+            place: *place,
+            score: snake.stats.score,
+            food_eaten: snake.stats.food_eaten,
+            kills: snake.stats.kills,
+            ticks_alive: snake.ticks_alive,
+            alive: snake.alive,
+            death: snake.death.map(|death| death.to_string()),
+            disqualified: snake.disqualified.map(|reason| reason.to_string()),
+        });
+    }
+    report.games.push(GameRecord {
+        round,
+        seed: contest.seed,
+        ticks: contest.ticks,
+        snakes,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bot::BotLevel, sim::SimStats};
+
+    fn ratings(elos: &[f64]) -> TournamentReport {
+        TournamentReport {
+            standings: elos
+                .iter()
+                .enumerate()
+                .map(|(index, elo)| Standing {
+                    elo: *elo,
+                    ..Standing::new(format!("bot {index}"))
+                })
+                .collect(),
+            games: Vec::new(),
+        }
+    }
+
+    /// Game where snakes lived for `ticks`, longest living is alive
+    fn contest(ticks: &[u64]) -> ContestReport {
+        let last = ticks.iter().copied().max().unwrap_or_default();
+        ContestReport {
+            seed: 0,
+            ticks: last,
+            snakes: ticks
+                .iter()
+                .map(|ticks| ContestSnake {
+                    name: String::new(),
+                    stats: SimStats::default(),
+                    alive: *ticks == last,
+                    ticks_alive: *ticks,
+                    death: None,
+                    disqualified: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn total_elo(report: &TournamentReport) -> f64 {
+        report.standings.iter().map(|standing| standing.elo).sum()
+    }
+
+    #[test]
+    fn decisive_game_moves_elo_from_loser_to_winner() {
+        let mut report = ratings(&[1500., 1600., 1450.]);
+        let total = total_elo(&report);
+        record_game(&mut report, 0, &[0, 1, 2], contest(&[30, 10, 20]));
+        assert!((total_elo(&report) - total).abs() < 1e-9);
+
+        let [winner, loser, middle] = &report.standings[..] else {
+            unreachable!();
+        };
+        assert!(winner.elo > 1500.);
+        assert!(loser.elo < 1600.);
+        assert_eq!((winner.wins, loser.wins, middle.wins), (1, 0, 0));
+        assert_eq!((winner.points, loser.points, middle.points), (1., 0., 0.5));
+        let places: Vec<_> = report.games[0].snakes.iter().map(|s| s.place).collect();
+        assert_eq!(places, [1, 3, 2]);
+    }
+
+    #[test]
+    fn draw_shares_place_without_win() {
+        let mut report = ratings(&[1500., 1500.]);
+        record_game(&mut report, 0, &[0, 1], contest(&[10, 10]));
+        for standing in &report.standings {
+            assert_eq!(standing.elo, 1500.);
+            assert_eq!(standing.points, 0.5);
+            assert_eq!(standing.wins, 0);
+        }
+        assert!(report.games[0].snakes.iter().all(|snake| snake.place == 1));
+
+        // Draw with stronger entrant is a gain
+        let mut report = ratings(&[1400., 1600.]);
+        record_game(&mut report, 0, &[0, 1], contest(&[10, 10]));
+        assert!(report.standings[0].elo > 1400.);
+        assert!((total_elo(&report) - 3000.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn swiss_groups_avoid_rematch() {
+        let report = ratings(&[1600., 1550., 1500., 1450.]);
+        let mut played = vec![vec![false; 4]; 4];
+        played[0][1] = true;
+        played[1][0] = true;
+        let groups = swiss_groups(&report.standings, &played, 2);
+        assert_eq!(groups, [vec![0, 2], vec![1, 3]]);
+
+        // Everybody played everybody, pairing falls back to closest
+        let played = vec![vec![true; 4]; 4];
+        let groups = swiss_groups(&report.standings, &played, 2);
+        assert_eq!(groups, [vec![0, 1], vec![2, 3]]);
+    }
+
+    #[test]
+    fn swiss_rounds_do_not_repeat_pairs() {
+        let config = GameConfig {
+            map_width: 8,
+            map_height: 8,
+            ..GameConfig::default()
+        };
+        let entrants = [
+            BotLevel::Easy,
+            BotLevel::Normal,
+            BotLevel::Hard,
+            BotLevel::Easy,
+        ]
+        .map(Contestant::Builtin);
+        let options = TournamentOptions {
+            pairing: Pairing::Swiss,
+            games: 3,
+            max_ticks: 50,
+            ..TournamentOptions::default()
+        };
+        let report = run_tournament(&config, &entrants, &options).unwrap();
+        assert_eq!(report.games.len(), 6);
+        let mut pairs: Vec<_> = report
+            .games
+            .iter()
+            .map(|game| {
+                let (a, b) = (game.snakes[0].entrant, game.snakes[1].entrant);
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        pairs.sort();
+        pairs.dedup();
+        assert_eq!(pairs.len(), 6);
+        assert!(report.standings.iter().all(|standing| standing.games == 3));
+    }
+}